@@ -1,12 +1,39 @@
+pub mod ar;
+pub mod audio;
+pub mod blocking;
+pub mod columnar;
+pub mod cpio;
 pub mod custom;
 pub mod decompress;
+pub mod ebook;
+pub mod evtx;
 pub mod ffmpeg;
+pub mod html;
+pub mod imagemeta;
+pub mod iso;
+pub mod journal;
+pub mod jsonflat;
 pub mod mbox;
+pub mod msoffice;
+pub mod notebook;
+pub mod ocr;
+pub mod odf;
+pub mod ooxml;
+pub mod outlook;
+pub mod pdf;
+pub mod plist;
 pub mod postproc;
+pub mod rar;
+pub mod rpm;
+pub mod rtf;
 use std::sync::Arc;
+pub mod sevenzip;
+pub mod spreadsheet;
 pub mod sqlite;
+pub mod subtitles;
 pub mod tar;
 pub mod writing;
+pub mod xml;
 pub mod zip;
 use crate::{adapted_iter::AdaptedFilesIterBox, config::RgaConfig, matching::*};
 use anyhow::{Context, Result, format_err};
@@ -110,6 +137,13 @@ pub struct AdaptInfo {
 type AdaptersTuple = (Vec<Arc<dyn FileAdapter>>, Vec<Arc<dyn FileAdapter>>);
 
 pub fn get_all_adapters(custom_adapters: Option<Vec<CustomAdapterConfig>>) -> AdaptersTuple {
+    all_adapters(custom_adapters, pdf::pdftotext_found())
+}
+
+fn all_adapters(
+    custom_adapters: Option<Vec<CustomAdapterConfig>>,
+    pdftotext_found: bool,
+) -> AdaptersTuple {
     // order in descending priority
     let mut adapters: Vec<Arc<dyn FileAdapter>> = vec![];
     if let Some(custom_adapters) = custom_adapters {
@@ -120,13 +154,42 @@ pub fn get_all_adapters(custom_adapters: Option<Vec<CustomAdapterConfig>>) -> Ad
 
     let internal_adapters: Vec<Arc<dyn FileAdapter>> = vec![
         Arc::new(PostprocPageBreaks::default()),
+        Arc::new(pdf::PdfAdapter::new()),
         Arc::new(ffmpeg::FFmpegAdapter::new()),
+        Arc::new(audio::AudioAdapter::new()),
+        Arc::new(subtitles::SubtitlesAdapter::new()),
         Arc::new(zip::ZipAdapter::new()),
         Arc::new(decompress::DecompressAdapter::new()),
         Arc::new(mbox::MboxAdapter::new()),
+        Arc::new(outlook::OutlookAdapter::new()),
+        Arc::new(html::HtmlAdapter::new()),
+        Arc::new(notebook::NotebookAdapter::new()),
+        Arc::new(rtf::RtfAdapter::new()),
+        Arc::new(msoffice::MsOfficeAdapter::new()),
         Arc::new(tar::TarAdapter::new()),
+        Arc::new(sevenzip::SevenZipAdapter::new()),
+        Arc::new(rar::RarAdapter::new()),
+        Arc::new(ar::ArAdapter::new()),
+        Arc::new(rpm::RpmAdapter::new()),
+        Arc::new(cpio::CpioAdapter::new()),
+        Arc::new(iso::IsoAdapter::new()),
         Arc::new(sqlite::SqliteAdapter::new()),
+        Arc::new(jsonflat::JsonFlatAdapter::new()),
+        Arc::new(plist::PlistAdapter::new()),
+        Arc::new(evtx::EvtxAdapter::new()),
+        Arc::new(journal::JournalAdapter::new()),
+        Arc::new(columnar::ColumnarAdapter::new()),
+        Arc::new(imagemeta::ImageMetaAdapter::new()),
+        Arc::new(ocr::OcrAdapter::new()),
     ];
+    // native extractors for formats the spawning adapters also handle, so they take precedence
+    let document_adapters: Vec<Arc<dyn FileAdapter>> = vec![
+        Arc::new(ooxml::OoxmlAdapter::new()),
+        Arc::new(spreadsheet::SpreadsheetAdapter::new()),
+        Arc::new(odf::OdfAdapter::new()),
+        Arc::new(ebook::EbookAdapter::new()),
+    ];
+    adapters.extend(document_adapters);
     adapters.extend(
         BUILTIN_SPAWNING_ADAPTERS
             .iter()
@@ -134,9 +197,15 @@ pub fn get_all_adapters(custom_adapters: Option<Vec<CustomAdapterConfig>>) -> Ad
     );
     adapters.extend(internal_adapters);
 
-    adapters
-        .into_iter()
-        .partition(|e| !e.metadata().disabled_by_default)
+    adapters.into_iter().partition(|e| {
+        let meta = e.metadata();
+        // without pdftotext, poppler is replaced by the native pdf adapter
+        match meta.name.as_str() {
+            "poppler" if !pdftotext_found => false,
+            "pdf" if !pdftotext_found => true,
+            _ => !meta.disabled_by_default,
+        }
+    })
 }
 
 /**
@@ -214,3 +283,28 @@ pub fn get_adapters_filtered<T: AsRef<str>>(
     );
     Ok(adapters)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matching::{FileMeta, adapter_matcher};
+    use pretty_assertions::assert_eq;
+
+    fn pdf_adapter_name(pdftotext_found: bool) -> Result<String> {
+        let (enabled, _) = all_adapters(None, pdftotext_found);
+        let (adapter, _) = adapter_matcher(&enabled, false)?(FileMeta {
+            lossy_filename: "short.pdf".to_string(),
+            mimetype: None,
+        })
+        .expect("no adapter for pdf files");
+        Ok(adapter.metadata().name.clone())
+    }
+
+    #[test]
+    fn pdf_fallback() -> Result<()> {
+        assert_eq!(pdf_adapter_name(true)?, "poppler");
+        // the native adapter wins when poppler is unavailable
+        assert_eq!(pdf_adapter_name(false)?, "pdf");
+        Ok(())
+    }
+}
@@ -0,0 +1,105 @@
+use super::blocking::{EntrySender, blocking_entries};
+use super::*;
+use crate::print_bytes;
+use anyhow::Result;
+use lazy_static::lazy_static;
+use tokio_util::io::SyncIoBridge;
+
+static EXTENSIONS: &[&str] = &["deb", "udeb", "ar"];
+static MIME_TYPES: &[&str] = &[
+    "application/vnd.debian.binary-package",
+    "application/x-archive",
+];
+
+lazy_static! {
+    static ref METADATA: AdapterMeta = AdapterMeta {
+        name: "ar".to_owned(),
+        version: 1,
+        description:
+            "Reads an ar archive (e.g. a Debian .deb package) as a stream and recurses down into its contents"
+                .to_owned(),
+        recurses: true,
+        fast_matchers: EXTENSIONS
+            .iter()
+            .map(|s| FastFileMatcher::FileExtension(s.to_string()))
+            .collect(),
+        slow_matchers: Some(
+            MIME_TYPES
+                .iter()
+                .map(|s| FileMatcher::MimeType(s.to_string()))
+                .collect()
+        ),
+        keep_fast_matchers_if_accurate: true,
+        disabled_by_default: false
+    };
+}
+#[derive(Default, Clone)]
+pub struct ArAdapter;
+
+impl ArAdapter {
+    pub fn new() -> Self {
+        Self
+    }
+}
+impl GetMetadata for ArAdapter {
+    fn metadata(&self) -> &AdapterMeta {
+        &METADATA
+    }
+}
+
+fn read_entries(ai: AdaptInfo, entries: &EntrySender) -> Result<()> {
+    let mut archive = ::ar::Archive::new(SyncIoBridge::new(ai.inp));
+    while let Some(entry) = archive.next_entry() {
+        let entry = entry?;
+        let path = PathBuf::from(String::from_utf8_lossy(entry.header().identifier()).as_ref());
+        debug!(
+            "{}{}|{}: {}",
+            ai.line_prefix,
+            ai.filepath_hint.display(),
+            path.display(),
+            print_bytes(entry.header().size() as f64)
+        );
+        // for .deb files, control.tar.* and data.tar.* are handled by the decompress and tar adapters
+        entries.send(path, entry)?;
+    }
+    Ok(())
+}
+
+#[async_trait]
+impl FileAdapter for ArAdapter {
+    async fn adapt(
+        &self,
+        ai: AdaptInfo,
+        _detection_reason: &FileMatcher,
+    ) -> Result<AdaptedFilesIterBox> {
+        Ok(blocking_entries(ai, read_entries))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{preproc::loop_adapt, test_utils::*};
+    use pretty_assertions::assert_eq;
+    use tokio::fs::File;
+
+    #[tokio::test]
+    async fn deb() -> Result<()> {
+        let filepath = test_data_dir().join("hello.deb");
+        let (a, d) = simple_adapt_info(&filepath, Box::pin(File::open(&filepath).await?));
+        let o = adapted_to_vec(loop_adapt(&ArAdapter::new(), d, a).await?).await?;
+        assert_eq!(
+            String::from_utf8(o)?,
+            "PREFIX:debian-binary: 2.0
+PREFIX:debian-binary: 
+PREFIX:control.tar.gz: ./control: Package: hello
+PREFIX:control.tar.gz: ./control: Version: 1.0
+PREFIX:control.tar.gz: ./control: Description: greets the world
+PREFIX:control.tar.gz: ./control: 
+PREFIX:data.tar.xz: ./usr/share/doc/hello/copyright: License: GPL-3.0-or-later
+PREFIX:data.tar.xz: ./usr/share/doc/hello/copyright: 
+"
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,606 @@
+use super::writing::WritingFileAdapter;
+use super::xml::write_lines;
+use super::*;
+use anyhow::{Result, bail};
+use lazy_static::lazy_static;
+use std::io::{BufWriter, Cursor, Read, Write};
+use tokio::io::AsyncWrite;
+use tokio_util::io::SyncIoBridge;
+
+static EXTENSIONS: &[&str] = &["mp3", "flac", "ogg", "oga", "opus", "m4a", "m4b"];
+static MIME_TYPES: &[&str] = &[
+    "audio/mpeg",
+    "audio/flac",
+    "audio/x-flac",
+    "audio/ogg",
+    "audio/opus",
+    "audio/mp4",
+    "audio/x-m4a",
+];
+
+lazy_static! {
+    static ref METADATA: AdapterMeta = AdapterMeta {
+        name: "audio".to_owned(),
+        version: 1,
+        description: "Reads ID3v2 tags, Vorbis comments and MP4 metadata atoms of audio files (including lyrics) without ffmpeg".to_owned(),
+        recurses: false,
+        fast_matchers: EXTENSIONS
+            .iter()
+            .map(|s| FastFileMatcher::FileExtension(s.to_string()))
+            .collect(),
+        slow_matchers: Some(
+            MIME_TYPES
+                .iter()
+                .map(|s| FileMatcher::MimeType(s.to_string()))
+                .collect()
+        ),
+        keep_fast_matchers_if_accurate: true,
+        disabled_by_default: false
+    };
+}
+
+#[derive(Default, Clone)]
+pub struct AudioAdapter;
+
+impl AudioAdapter {
+    pub fn new() -> Self {
+        Self
+    }
+}
+impl GetMetadata for AudioAdapter {
+    fn metadata(&self) -> &AdapterMeta {
+        &METADATA
+    }
+}
+
+/// Tags larger than this are considered corrupt
+const MAX_TAG_SIZE: u64 = 64 * 1024 * 1024;
+
+/// Reads `len` bytes, without trusting `len` for the allocation
+fn read_vec(inp: &mut impl Read, len: u64) -> Result<Vec<u8>> {
+    if len > MAX_TAG_SIZE {
+        bail!("tag of {len} bytes is too large");
+    }
+    let mut data = Vec::new();
+    inp.take(len).read_to_end(&mut data)?;
+    if data.len() as u64 != len {
+        bail!("unexpected end of file");
+    }
+    Ok(data)
+}
+
+fn skip(inp: &mut impl Read, len: u64) -> Result<()> {
+    std::io::copy(&mut inp.take(len), &mut std::io::sink())?;
+    Ok(())
+}
+
+/// Reads as many bytes as available up to the size of `buf`
+fn read_up_to(inp: &mut impl Read, buf: &mut [u8]) -> Result<usize> {
+    let mut read = 0;
+    while read < buf.len() {
+        match inp.read(&mut buf[read..])? {
+            0 => break,
+            n => read += n,
+        }
+    }
+    Ok(read)
+}
+
+fn syncsafe(b: &[u8]) -> u32 {
+    b.iter().fold(0, |acc, &b| (acc << 7) | u32::from(b & 0x7f))
+}
+
+/// Reverts the ID3 unsynchronisation scheme, which inserts a zero after each 0xff
+fn resync(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len());
+    for (i, &b) in data.iter().enumerate() {
+        if !(b == 0 && i > 0 && data[i - 1] == 0xff) {
+            out.push(b);
+        }
+    }
+    out
+}
+
+/// Decodes an ID3 string in the given text encoding
+fn id3_string(encoding: u8, data: &[u8]) -> String {
+    let s = match encoding {
+        1 => encoding_rs::UTF_16LE.decode(data).0,
+        2 => encoding_rs::UTF_16BE.decode_without_bom_handling(data).0,
+        3 => String::from_utf8_lossy(data),
+        _ => encoding_rs::mem::decode_latin1(data),
+    };
+    s.trim_end_matches('\0').to_owned()
+}
+
+/// Splits ID3 text at the null terminators of the given text encoding
+fn id3_split(encoding: u8, data: &[u8]) -> Vec<&[u8]> {
+    if matches!(encoding, 1 | 2) {
+        let mut parts = Vec::new();
+        let mut start = 0;
+        let mut i = 0;
+        while i + 1 < data.len() {
+            if data[i] == 0 && data[i + 1] == 0 {
+                parts.push(&data[start..i]);
+                start = i + 2;
+            }
+            i += 2;
+        }
+        parts.push(&data[start.min(data.len())..]);
+        parts
+    } else {
+        data.split(|&b| b == 0).collect()
+    }
+}
+
+fn write_id3_frame(out: &mut impl Write, id: &str, data: &[u8]) -> Result<()> {
+    let Some((&encoding, text)) = data.split_first() else {
+        return Ok(());
+    };
+    match id {
+        "TXXX" | "TXX" | "WXXX" | "WXX" => {
+            let parts = id3_split(encoding, text);
+            if let [description, values @ ..] = &parts[..] {
+                let key = format!("id3.{id}.{}: ", id3_string(encoding, description));
+                for value in values {
+                    // urls are always latin1
+                    let value = if id.starts_with('W') {
+                        id3_string(0, value)
+                    } else {
+                        id3_string(encoding, value)
+                    };
+                    write_lines(out, &key, &value)?;
+                }
+            }
+        }
+        // language and a short content description before the text
+        "COMM" | "COM" | "USLT" | "ULT" => {
+            let Some(text) = text.get(3..) else {
+                return Ok(());
+            };
+            let parts = id3_split(encoding, text);
+            if let [_description, values @ ..] = &parts[..] {
+                for value in values {
+                    write_lines(out, &format!("id3.{id}: "), &id3_string(encoding, value))?;
+                }
+            }
+        }
+        _ if id.starts_with('T') => {
+            // version 4 allows multiple null separated values
+            for value in id3_split(encoding, text) {
+                write_lines(out, &format!("id3.{id}: "), &id3_string(encoding, value))?;
+            }
+        }
+        _ if id.starts_with('W') => {
+            write_lines(out, &format!("id3.{id}: "), &id3_string(0, data))?;
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Writes the text, comment, lyrics and url frames of an ID3v2 tag after the "ID3" magic
+fn write_id3v2(out: &mut impl Write, inp: &mut impl Read) -> Result<()> {
+    let mut header = [0u8; 10];
+    inp.read_exact(&mut header)?;
+    let [_, _, _, major, _, flags, size @ ..] = header;
+    let mut tag = read_vec(inp, syncsafe(&size).into())?;
+    if flags & 0x80 != 0 && major < 4 {
+        tag = resync(&tag);
+    }
+    let mut pos = 0;
+    if flags & 0x40 != 0 {
+        // extended header
+        let size = tag.get(..4).unwrap_or_default();
+        pos = match major {
+            3 => u32::from_be_bytes(size.try_into()?) as usize + 4,
+            _ => syncsafe(size) as usize,
+        };
+    }
+    let (id_len, header_len) = if major == 2 { (3, 6) } else { (4, 10) };
+    while let Some(frame_header) = tag.get(pos..pos + header_len) {
+        // padding
+        if frame_header[0] == 0 {
+            break;
+        }
+        let id = String::from_utf8_lossy(&frame_header[..id_len]).into_owned();
+        let size = match major {
+            2 => u32::from_be_bytes([0, frame_header[3], frame_header[4], frame_header[5]]),
+            3 => u32::from_be_bytes(frame_header[4..8].try_into()?),
+            _ => syncsafe(&frame_header[4..8]),
+        } as usize;
+        let flags = frame_header
+            .get(8..10)
+            .map_or(0, |f| u16::from_be_bytes([f[0], f[1]]));
+        pos += header_len;
+        let Some(data) = tag.get(pos..pos + size) else {
+            break;
+        };
+        pos += size;
+        let (compressed_or_encrypted, unsynchronised, data_length) = match major {
+            3 => (flags & 0x00c0 != 0, false, false),
+            4 => (
+                flags & 0x000c != 0,
+                flags & 0x0002 != 0,
+                flags & 0x0001 != 0,
+            ),
+            _ => (false, false, false),
+        };
+        if compressed_or_encrypted {
+            continue;
+        }
+        let data = if unsynchronised {
+            resync(data)
+        } else {
+            data.to_vec()
+        };
+        let data = if data_length {
+            data.get(4..).unwrap_or_default()
+        } else {
+            &data[..]
+        };
+        write_id3_frame(out, &id, data)?;
+    }
+    Ok(())
+}
+
+/// Writes a Vorbis comment block as `vorbis.KEY: value`
+fn write_vorbis_comments(out: &mut impl Write, data: &[u8]) -> Result<()> {
+    fn read_u32(inp: &mut &[u8]) -> Result<u32> {
+        let mut b = [0u8; 4];
+        inp.read_exact(&mut b)?;
+        Ok(u32::from_le_bytes(b))
+    }
+    let mut inp = data;
+    let vendor_len = read_u32(&mut inp)?;
+    skip(&mut inp, vendor_len.into())?;
+    let count = read_u32(&mut inp)?;
+    for _ in 0..count {
+        let len = read_u32(&mut inp)?;
+        let comment = read_vec(&mut inp, len.into())?;
+        let comment = String::from_utf8_lossy(&comment);
+        let Some((key, value)) = comment.split_once('=') else {
+            continue;
+        };
+        let key = key.to_ascii_uppercase();
+        // base64 encoded cover art
+        if key == "METADATA_BLOCK_PICTURE" || key == "COVERART" {
+            continue;
+        }
+        write_lines(out, &format!("vorbis.{key}: "), value)?;
+    }
+    Ok(())
+}
+
+/// Reads the metadata blocks after the "fLaC" magic
+fn write_flac(out: &mut impl Write, inp: &mut impl Read) -> Result<()> {
+    skip(inp, 4)?;
+    loop {
+        let mut header = [0u8; 4];
+        inp.read_exact(&mut header)?;
+        let [kind, len @ ..] = header;
+        let len = u32::from_be_bytes([0, len[0], len[1], len[2]]);
+        if kind & 0x7f == 4 {
+            write_vorbis_comments(out, &read_vec(inp, len.into())?)?;
+        } else {
+            skip(inp, len.into())?;
+        }
+        if kind & 0x80 != 0 {
+            return Ok(());
+        }
+    }
+}
+
+/// Reads the first two packets of the first logical bitstream of an ogg file.
+///
+/// The second packet contains the comments for vorbis, opus, flac and speex.
+fn write_ogg(out: &mut impl Write, inp: &mut impl Read) -> Result<()> {
+    let mut packets: Vec<Vec<u8>> = vec![Vec::new()];
+    let mut first_serial = None;
+    while packets.len() < 3 {
+        let mut header = [0u8; 27];
+        if read_up_to(inp, &mut header)? < header.len() {
+            break;
+        }
+        if &header[..4] != b"OggS" {
+            bail!("invalid ogg page");
+        }
+        let serial = u32::from_le_bytes(header[14..18].try_into()?);
+        let mut lacing = vec![0u8; header[26].into()];
+        inp.read_exact(&mut lacing)?;
+        let body = read_vec(inp, lacing.iter().map(|&l| u64::from(l)).sum())?;
+        if *first_serial.get_or_insert(serial) != serial {
+            continue;
+        }
+        let mut pos = 0;
+        for len in lacing {
+            let len = usize::from(len);
+            let packet = packets.last_mut().expect("never empty");
+            packet.extend_from_slice(&body[pos..pos + len]);
+            if packet.len() as u64 > MAX_TAG_SIZE {
+                bail!("ogg packet is too large");
+            }
+            pos += len;
+            // a segment shorter than 255 bytes ends the packet
+            if len < 255 {
+                packets.push(Vec::new());
+            }
+        }
+    }
+    let Some(comments) = packets.get(1) else {
+        return Ok(());
+    };
+    let comments = if let Some(c) = comments.strip_prefix(b"\x03vorbis") {
+        c
+    } else if let Some(c) = comments.strip_prefix(b"OpusTags") {
+        c
+    } else if packets[0].starts_with(b"\x7fFLAC") {
+        // a flac metadata block header
+        comments.get(4..).unwrap_or_default()
+    } else if packets[0].starts_with(b"Speex   ") {
+        comments
+    } else {
+        return Ok(());
+    };
+    write_vorbis_comments(out, comments)
+}
+
+/// Returns a readable name for an iTunes metadata item
+fn mp4_item_name(kind: &[u8]) -> String {
+    let name = match kind {
+        b"\xa9nam" => "title",
+        b"\xa9ART" => "artist",
+        b"aART" => "album_artist",
+        b"\xa9alb" => "album",
+        b"\xa9day" => "date",
+        b"\xa9gen" => "genre",
+        b"\xa9wrt" => "composer",
+        b"\xa9cmt" => "comment",
+        b"\xa9lyr" => "lyrics",
+        b"\xa9too" => "encoder",
+        b"\xa9grp" => "grouping",
+        b"cprt" => "copyright",
+        b"desc" => "description",
+        b"ldes" => "long_description",
+        b"tvsh" => "show",
+        b"trkn" => "track",
+        b"disk" => "disc",
+        // 0xa9 is the copyright sign in latin1
+        _ => return encoding_rs::mem::decode_latin1(kind).into_owned(),
+    };
+    name.to_owned()
+}
+
+/// Iterates over the boxes in the body of an mp4 box as (type, body)
+fn mp4_boxes(mut data: &[u8]) -> impl Iterator<Item = (&[u8], &[u8])> {
+    std::iter::from_fn(move || {
+        let size = u32::from_be_bytes(data.get(..4)?.try_into().ok()?) as usize;
+        let kind = data.get(4..8)?;
+        let body = data.get(8..size)?;
+        let this = (kind, body);
+        data = &data[size..];
+        Some(this)
+    })
+}
+
+fn mp4_child<'a>(data: &'a [u8], kind: &[u8]) -> Option<&'a [u8]> {
+    mp4_boxes(data).find(|(k, _)| *k == kind).map(|(_, b)| b)
+}
+
+/// Formats the payload of an ilst `data` box
+fn mp4_value(kind: &[u8], data_type: u32, payload: &[u8]) -> Option<String> {
+    match (data_type, kind) {
+        (1, _) => Some(String::from_utf8_lossy(payload).into_owned()),
+        (2, _) => Some(
+            encoding_rs::UTF_16BE
+                .decode_without_bom_handling(payload)
+                .0
+                .into_owned(),
+        ),
+        // track and disc number
+        (0, b"trkn" | b"disk") => {
+            let n = u16::from_be_bytes(payload.get(2..4)?.try_into().ok()?);
+            let total = u16::from_be_bytes(payload.get(4..6)?.try_into().ok()?);
+            Some(format!("{n}/{total}"))
+        }
+        // an empty payload would shift by 64 bits
+        (21, _) if !payload.is_empty() => {
+            let mut b = [0u8; 8];
+            let start = 8usize.checked_sub(payload.len())?;
+            b[start..].copy_from_slice(payload);
+            // sign extend
+            let shift = start * 8;
+            Some(((i64::from_be_bytes(b) << shift) >> shift).to_string())
+        }
+        (22, _) => {
+            let mut b = [0u8; 8];
+            b[8usize.checked_sub(payload.len())?..].copy_from_slice(payload);
+            Some(u64::from_be_bytes(b).to_string())
+        }
+        // images and binary data
+        _ => None,
+    }
+}
+
+fn write_mp4_moov(out: &mut impl Write, moov: &[u8]) -> Result<()> {
+    let Some(meta) = mp4_child(moov, b"udta").and_then(|udta| mp4_child(udta, b"meta")) else {
+        return Ok(());
+    };
+    // meta is a full box with version and flags, except in some quicktime files
+    let meta = match meta.get(4..8) {
+        Some(b"hdlr") => meta,
+        _ => meta.get(4..).unwrap_or_default(),
+    };
+    let Some(ilst) = mp4_child(meta, b"ilst") else {
+        return Ok(());
+    };
+    for (kind, item) in mp4_boxes(ilst) {
+        let mut name = mp4_item_name(kind);
+        for (child, body) in mp4_boxes(item) {
+            match child {
+                // freeform items
+                b"name" => {
+                    name = String::from_utf8_lossy(body.get(4..).unwrap_or_default()).into_owned()
+                }
+                b"data" => {
+                    let Some(data_type) = body.get(..4) else {
+                        continue;
+                    };
+                    let data_type = u32::from_be_bytes(data_type.try_into()?) & 0xffffff;
+                    let payload = body.get(8..).unwrap_or_default();
+                    if let Some(value) = mp4_value(kind, data_type, payload) {
+                        write_lines(out, &format!("mp4.{name}: "), &value)?;
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Reads the top level boxes of an mp4 file until the `moov` box, skipping the media data
+fn write_mp4(out: &mut impl Write, inp: &mut impl Read) -> Result<()> {
+    loop {
+        let mut header = [0u8; 8];
+        if read_up_to(inp, &mut header)? < header.len() {
+            return Ok(());
+        }
+        let size = u32::from_be_bytes(header[..4].try_into()?);
+        let body_size = match size {
+            // extends to the end of the file
+            0 => return Ok(()),
+            1 => {
+                let mut large = [0u8; 8];
+                inp.read_exact(&mut large)?;
+                u64::from_be_bytes(large).saturating_sub(16)
+            }
+            size => u64::from(size).saturating_sub(8),
+        };
+        if &header[4..] == b"moov" {
+            return write_mp4_moov(out, &read_vec(inp, body_size)?);
+        }
+        skip(inp, body_size)?;
+    }
+}
+
+fn synchronous_dump_audio(ai: AdaptInfo, out: impl Write) -> Result<()> {
+    let mut out = BufWriter::new(out);
+    let mut inp = SyncIoBridge::new(ai.inp);
+    let mut magic = [0u8; 8];
+    let len = read_up_to(&mut inp, &mut magic)?;
+    let mut inp = Cursor::new(magic[..len].to_vec()).chain(inp);
+    if magic.starts_with(b"ID3") {
+        write_id3v2(&mut out, &mut inp)?;
+        // flac files sometimes start with an id3 tag
+        let len = read_up_to(&mut inp, &mut magic[..4])?;
+        if magic[..len] == *b"fLaC" {
+            write_flac(&mut out, &mut Cursor::new(b"fLaC").chain(inp))?;
+        }
+    } else if magic.starts_with(b"fLaC") {
+        write_flac(&mut out, &mut inp)?;
+    } else if magic.starts_with(b"OggS") {
+        write_ogg(&mut out, &mut inp)?;
+    } else if &magic[4..] == b"ftyp" {
+        write_mp4(&mut out, &mut inp)?;
+    }
+    out.flush()?;
+    Ok(())
+}
+
+#[async_trait]
+impl WritingFileAdapter for AudioAdapter {
+    async fn adapt_write(
+        ai: AdaptInfo,
+        _detection_reason: &FileMatcher,
+        oup: Pin<Box<dyn AsyncWrite + Send>>,
+    ) -> Result<()> {
+        let oup_sync = SyncIoBridge::new(oup);
+        tokio::task::spawn_blocking(|| synchronous_dump_audio(ai, oup_sync))
+            .await?
+            .context("in synchronous audio task")?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use pretty_assertions::assert_eq;
+
+    #[tokio::test]
+    async fn id3() -> Result<()> {
+        assert_eq!(
+            adapt_test_file(&AudioAdapter::new(), "hello.mp3", false).await?,
+            "PREFIX:id3.TIT2: Hello Song
+PREFIX:id3.TPE1: First Artist
+PREFIX:id3.TPE1: Second Artist
+PREFIX:id3.TALB: Älbum
+PREFIX:id3.TXXX.MOOD: calm
+PREFIX:id3.COMM: a comment
+PREFIX:id3.USLT: first lyric line
+PREFIX:id3.USLT: second lyric line
+PREFIX:
+"
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn flac() -> Result<()> {
+        assert_eq!(
+            adapt_test_file(&AudioAdapter::new(), "hello.flac", false).await?,
+            "PREFIX:vorbis.TITLE: Hello Song
+PREFIX:vorbis.ARTIST: Flac Artist
+PREFIX:vorbis.LYRICS: la la la
+PREFIX:vorbis.LYRICS: second line
+PREFIX:
+"
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn opus() -> Result<()> {
+        assert_eq!(
+            adapt_test_file(&AudioAdapter::new(), "hello.opus", false).await?,
+            format!(
+                "PREFIX:vorbis.TITLE: Hello Opus
+PREFIX:vorbis.ARTIST: Opus Artist
+PREFIX:vorbis.LYRICS: {}
+PREFIX:
+",
+                "long lyrics ".repeat(30).trim_end()
+            )
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn mp4_integers() {
+        assert_eq!(
+            mp4_value(b"tmpo", 21, &[0x00, 0x78]).as_deref(),
+            Some("120")
+        );
+        assert_eq!(mp4_value(b"tmpo", 21, &[0xff, 0xfe]).as_deref(), Some("-2"));
+        assert_eq!(mp4_value(b"tmpo", 21, &[]), None);
+        assert_eq!(mp4_value(b"tmpo", 21, &[0; 9]), None);
+        assert_eq!(mp4_value(b"plID", 22, &[1, 0]).as_deref(), Some("256"));
+    }
+
+    #[tokio::test]
+    async fn m4a() -> Result<()> {
+        assert_eq!(
+            adapt_test_file(&AudioAdapter::new(), "hello.m4a", false).await?,
+            "PREFIX:mp4.title: Hello M4A
+PREFIX:mp4.artist: M4A Artist
+PREFIX:mp4.track: 3/12
+PREFIX:mp4.lyrics: m4a lyrics
+PREFIX:mp4.lyrics: line two
+PREFIX:mp4.MOOD: happy
+PREFIX:
+"
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,143 @@
+//! Helpers for adapters that are built on synchronous (`std::io`) readers.
+//!
+//! Many archive and document libraries can only read from a `Read + Seek` file or a path on disk.
+//! These run on a blocking thread and hand their entries back to the async side one at a time.
+use super::*;
+use crate::print_bytes;
+use anyhow::Result;
+use async_stream::stream;
+use std::io::{ErrorKind, Read};
+use std::path::Path;
+use tokio::sync::mpsc;
+use tokio_util::io::SyncIoBridge;
+
+/// A file on disk with the contents of the input.
+///
+/// If the input is a real file it is used directly, otherwise (e.g. a file inside an archive)
+/// the input is spooled to a temporary file that is deleted when this is dropped.
+///
+/// Must be created from a blocking thread since it reads the async input synchronously.
+pub struct SpooledFile {
+    path: PathBuf,
+    _temp: Option<tempfile::TempPath>,
+}
+
+impl SpooledFile {
+    pub fn new(filepath_hint: &Path, is_real_file: bool, inp: ReadBox) -> Result<Self> {
+        if is_real_file {
+            return Ok(Self {
+                path: filepath_hint.to_owned(),
+                _temp: None,
+            });
+        }
+        let extension = filepath_hint
+            .extension()
+            .map(|e| format!(".{}", e.to_string_lossy()))
+            .unwrap_or_default();
+        let mut temp = tempfile::Builder::new()
+            .prefix("rga-")
+            .suffix(&extension)
+            .tempfile()
+            .context("creating temporary file")?;
+        let size = std::io::copy(&mut SyncIoBridge::new(inp), &mut temp)
+            .with_context(|| format!("spooling {} to disk", filepath_hint.display()))?;
+        debug!(
+            "spooled {} to {} ({})",
+            filepath_hint.display(),
+            temp.path().display(),
+            print_bytes(size as f64)
+        );
+        let temp = temp.into_temp_path();
+        Ok(Self {
+            path: temp.to_path_buf(),
+            _temp: Some(temp),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn open(&self) -> std::io::Result<std::fs::File> {
+        std::fs::File::open(&self.path)
+    }
+}
+
+/// Sends the entries found by a synchronous reader to the stream returned by [`blocking_entries`].
+pub struct EntrySender {
+    tx: mpsc::Sender<(PathBuf, PathBuf, ReadBox)>,
+}
+
+impl EntrySender {
+    /// Yields an entry and copies its contents to the consumer.
+    ///
+    /// Blocks until the entry has been read. If the consumer stops reading early, the rest of the entry is discarded.
+    pub fn send(&self, path: impl Into<PathBuf>, contents: impl Read) -> Result<()> {
+        let path = path.into();
+        self.send_as(path.clone(), path, contents)
+    }
+
+    /// Yields a `[rga: ...]` marker line in place of an entry that can't be extracted.
+    pub fn send_message(&self, path: &Path, message: &str) -> Result<()> {
+        // the .txt suffix keeps the entry's own adapter from running on the message
+        let filepath_hint = format!("{}.txt", path.to_string_lossy());
+        self.send_as(
+            path.to_owned(),
+            filepath_hint.into(),
+            format!("[rga: {message}]\n").as_bytes(),
+        )
+    }
+
+    /// Yields an entry that is shown as `path` in the line prefix, but passed to the adapters as `filepath_hint`.
+    pub fn send_as(
+        &self,
+        path: PathBuf,
+        filepath_hint: PathBuf,
+        mut contents: impl Read,
+    ) -> Result<()> {
+        let (w, r) = tokio::io::duplex(64 * 1024);
+        self.tx
+            .blocking_send((path, filepath_hint, Box::pin(r)))
+            .map_err(|_| format_err!("entry receiver was dropped"))?;
+        let mut w = SyncIoBridge::new(w);
+        match std::io::copy(&mut contents, &mut w) {
+            Ok(_) => {}
+            Err(e) if e.kind() == ErrorKind::BrokenPipe => {
+                // solid archives need the entry to be decoded fully before the next one
+                std::io::copy(&mut contents, &mut std::io::sink())?;
+            }
+            Err(e) => return Err(e.into()),
+        }
+        Ok(())
+    }
+}
+
+/// Runs `read_entries` on a blocking thread and yields each entry it sends as a file within the input,
+/// the same way the streaming archive adapters (tar, zip) do.
+pub fn blocking_entries<F>(ai: AdaptInfo, read_entries: F) -> AdaptedFilesIterBox
+where
+    F: FnOnce(AdaptInfo, &EntrySender) -> Result<()> + Send + 'static,
+{
+    let line_prefix = ai.line_prefix.clone();
+    let archive_recursion_depth = ai.archive_recursion_depth;
+    let postprocess = ai.postprocess;
+    let config = ai.config.clone();
+    let (tx, mut rx) = mpsc::channel(1);
+    let join = tokio::task::spawn_blocking(move || read_entries(ai, &EntrySender { tx }));
+    let s = stream! {
+        while let Some((path, filepath_hint, inp)) = rx.recv().await {
+            let new_line_prefix = format!("{}{}: ", line_prefix, path.display());
+            yield Ok(AdaptInfo {
+                filepath_hint,
+                is_real_file: false,
+                archive_recursion_depth: archive_recursion_depth + 1,
+                inp,
+                line_prefix: new_line_prefix,
+                postprocess,
+                config: config.clone(),
+            });
+        }
+        join.await??;
+    };
+    Box::pin(s)
+}
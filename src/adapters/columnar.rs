@@ -0,0 +1,273 @@
+use super::blocking::SpooledFile;
+use super::writing::WritingFileAdapter;
+use super::*;
+use anyhow::Result;
+use arrow_array::{Array, RecordBatch, RecordBatchReader, cast::AsArray};
+use arrow_cast::display::{ArrayFormatter, FormatOptions};
+use arrow_schema::{DataType, Schema};
+use lazy_static::lazy_static;
+use parquet::arrow::ProjectionMask;
+use parquet::arrow::arrow_reader::{ArrowReaderMetadata, ParquetRecordBatchReaderBuilder};
+use std::convert::TryInto;
+use std::io::{BufReader, BufWriter, Read, Write};
+use tokio::io::AsyncWrite;
+use tokio_util::io::SyncIoBridge;
+
+static EXTENSIONS: &[&str] = &["parquet", "arrow", "feather", "arrows"];
+static MIME_TYPES: &[&str] = &[
+    "application/vnd.apache.parquet",
+    "application/vnd.apache.arrow.file",
+    "application/vnd.apache.arrow.stream",
+];
+
+lazy_static! {
+    static ref METADATA: AdapterMeta = AdapterMeta {
+        name: "columnar".to_owned(),
+        version: 1,
+        description: "Dumps the rows of Parquet and Arrow IPC (Feather) files as column=value pairs, prefixed with the row group and row number".to_owned(),
+        recurses: false,
+        fast_matchers: EXTENSIONS
+            .iter()
+            .map(|s| FastFileMatcher::FileExtension(s.to_string()))
+            .collect(),
+        slow_matchers: Some(
+            MIME_TYPES
+                .iter()
+                .map(|s| FileMatcher::MimeType(s.to_string()))
+                .collect()
+        ),
+        keep_fast_matchers_if_accurate: true,
+        disabled_by_default: false
+    };
+}
+
+#[derive(Default, Clone)]
+pub struct ColumnarAdapter;
+
+impl ColumnarAdapter {
+    pub fn new() -> Self {
+        Self
+    }
+}
+impl GetMetadata for ColumnarAdapter {
+    fn metadata(&self) -> &AdapterMeta {
+        &METADATA
+    }
+}
+
+/// The indices of the top-level columns to dump, or None to dump all of them
+fn selected_columns(schema: &Schema, columns: &[String]) -> Option<Vec<usize>> {
+    if columns.is_empty() {
+        return None;
+    }
+    Some(
+        schema
+            .fields()
+            .iter()
+            .enumerate()
+            .filter(|(_, f)| columns.iter().any(|c| c == f.name()))
+            .map(|(i, _)| i)
+            .collect(),
+    )
+}
+
+fn format_blob(len: usize) -> String {
+    format!(
+        "[blob {}B]",
+        size_format::SizeFormatterSI::new(len.try_into().unwrap())
+    )
+}
+
+/// Formats a value the same way the sqlite adapter does
+fn format_value(array: &dyn Array, formatter: &ArrayFormatter, i: usize) -> String {
+    if array.is_null(i) {
+        return "NULL".to_owned();
+    }
+    match array.data_type() {
+        DataType::Utf8 | DataType::LargeUtf8 | DataType::Utf8View => {
+            format!("'{}'", formatter.value(i).to_string().replace('\'', "''"))
+        }
+        DataType::Binary => format_blob(array.as_binary::<i32>().value(i).len()),
+        DataType::LargeBinary => format_blob(array.as_binary::<i64>().value(i).len()),
+        DataType::BinaryView => format_blob(array.as_binary_view().value(i).len()),
+        DataType::FixedSizeBinary(len) => format_blob(*len as usize),
+        _ => formatter.value(i).to_string(),
+    }
+}
+
+/// Writes the rows of a batch, starting at the given row number
+fn write_batch(
+    out: &mut impl Write,
+    row_group: usize,
+    first_row: usize,
+    batch: &RecordBatch,
+) -> Result<()> {
+    let options = FormatOptions::default();
+    let formatters = batch
+        .columns()
+        .iter()
+        .map(|c| ArrayFormatter::try_new(c.as_ref(), &options))
+        .collect::<Result<Vec<_>, _>>()?;
+    let schema = batch.schema();
+    for row in 0..batch.num_rows() {
+        let row_str = schema
+            .fields()
+            .iter()
+            .zip(batch.columns())
+            .zip(&formatters)
+            .map(|((field, column), formatter)| {
+                format!(
+                    "{}={}",
+                    field.name(),
+                    format_value(column.as_ref(), formatter, row)
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+        writeln!(
+            out,
+            "row group {row_group}, row {}: {row_str}",
+            first_row + row
+        )?;
+    }
+    Ok(())
+}
+
+fn dump_parquet(file: std::fs::File, columns: &[String], out: &mut impl Write) -> Result<()> {
+    let metadata =
+        ArrowReaderMetadata::load(&file, Default::default()).context("reading parquet footer")?;
+    let projection = selected_columns(metadata.schema(), columns)
+        .map(|indices| ProjectionMask::roots(metadata.parquet_schema(), indices))
+        .unwrap_or(ProjectionMask::all());
+    let mut row = 0;
+    for row_group in 0..metadata.metadata().num_row_groups() {
+        let reader =
+            ParquetRecordBatchReaderBuilder::new_with_metadata(file.try_clone()?, metadata.clone())
+                .with_row_groups(vec![row_group])
+                .with_projection(projection.clone())
+                .build()?;
+        for batch in reader {
+            let batch = batch?;
+            write_batch(out, row_group, row, &batch)?;
+            row += batch.num_rows();
+        }
+    }
+    Ok(())
+}
+
+/// Dumps an Arrow IPC file or stream, where each record batch is a row group
+fn dump_arrow(
+    reader: impl RecordBatchReader,
+    columns: &[String],
+    out: &mut impl Write,
+) -> Result<()> {
+    let projection = selected_columns(&reader.schema(), columns);
+    let mut row = 0;
+    for (row_group, batch) in reader.enumerate() {
+        let mut batch = batch?;
+        if let Some(indices) = &projection {
+            batch = batch.project(indices)?;
+        }
+        write_batch(out, row_group, row, &batch)?;
+        row += batch.num_rows();
+    }
+    Ok(())
+}
+
+fn synchronous_dump_columnar(ai: AdaptInfo, out: impl Write) -> Result<()> {
+    let AdaptInfo {
+        is_real_file,
+        filepath_hint,
+        config,
+        inp,
+        ..
+    } = ai;
+    // the parquet metadata and the arrow file footer are at the end of the file
+    let spooled = SpooledFile::new(&filepath_hint, is_real_file, inp)?;
+    let mut magic = [0u8; 6];
+    let magic_len = spooled.open()?.read(&mut magic)?;
+    let columns = &config.adapter_options.columnar_columns;
+    let mut out = BufWriter::new(out);
+    if magic[..magic_len].starts_with(b"PAR1") {
+        dump_parquet(spooled.open()?, columns, &mut out)?;
+    } else if magic[..magic_len] == *b"ARROW1" {
+        let file = BufReader::new(spooled.open()?);
+        let reader =
+            arrow_ipc::reader::FileReader::try_new(file, None).context("reading arrow file")?;
+        dump_arrow(reader, columns, &mut out)?;
+    } else {
+        let stream = BufReader::new(spooled.open()?);
+        let reader = arrow_ipc::reader::StreamReader::try_new(stream, None)
+            .context("reading arrow stream")?;
+        dump_arrow(reader, columns, &mut out)?;
+    }
+    out.flush()?;
+    Ok(())
+}
+
+#[async_trait]
+impl WritingFileAdapter for ColumnarAdapter {
+    async fn adapt_write(
+        ai: AdaptInfo,
+        _detection_reason: &FileMatcher,
+        oup: Pin<Box<dyn AsyncWrite + Send>>,
+    ) -> Result<()> {
+        let oup_sync = SyncIoBridge::new(oup);
+        tokio::task::spawn_blocking(|| synchronous_dump_columnar(ai, oup_sync))
+            .await?
+            .context("in synchronous columnar task")?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{config::AdapterOptions, test_utils::*};
+    use pretty_assertions::assert_eq;
+
+    async fn adapt_file(name: &str, columns: &[&str]) -> Result<String> {
+        let config = RgaConfig {
+            adapter_options: AdapterOptions {
+                columnar_columns: columns.iter().map(|c| c.to_string()).collect(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        adapt_test_file_with_config(&ColumnarAdapter::new(), name, true, config).await
+    }
+
+    const ALL_COLUMNS: &str =
+        "PREFIX:row group 0, row 0: id=1, name='Alice', score=9.5, avatar=[blob 3B]
+PREFIX:row group 0, row 1: id=2, name='Bob''s', score=NULL, avatar=NULL
+PREFIX:row group 1, row 2: id=3, name=NULL, score=7.25, avatar=[blob 0B]
+PREFIX:
+";
+
+    #[tokio::test]
+    async fn parquet() -> Result<()> {
+        assert_eq!(adapt_file("hello.parquet", &[]).await?, ALL_COLUMNS);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn arrow() -> Result<()> {
+        assert_eq!(adapt_file("hello.arrow", &[]).await?, ALL_COLUMNS);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn selected_columns() -> Result<()> {
+        let expected = "PREFIX:row group 0, row 0: id=1, name='Alice'
+PREFIX:row group 0, row 1: id=2, name='Bob''s'
+PREFIX:row group 1, row 2: id=3, name=NULL
+PREFIX:
+";
+        assert_eq!(
+            adapt_file("hello.parquet", &["name", "id"]).await?,
+            expected
+        );
+        assert_eq!(adapt_file("hello.arrow", &["name", "id"]).await?, expected);
+        Ok(())
+    }
+}
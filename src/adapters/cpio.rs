@@ -0,0 +1,102 @@
+use super::blocking::{EntrySender, blocking_entries};
+use super::*;
+use crate::print_bytes;
+use ::cpio::NewcReader;
+use anyhow::Result;
+use lazy_static::lazy_static;
+use tokio_util::io::SyncIoBridge;
+
+static EXTENSIONS: &[&str] = &["cpio"];
+
+lazy_static! {
+    static ref METADATA: AdapterMeta = AdapterMeta {
+        name: "cpio".to_owned(),
+        version: 1,
+        description:
+            "Reads a cpio archive (newc format, as used by RPM payloads and initramfs) as a stream and recurses down into its contents"
+                .to_owned(),
+        recurses: true,
+        fast_matchers: EXTENSIONS
+            .iter()
+            .map(|s| FastFileMatcher::FileExtension(s.to_string()))
+            .collect(),
+        slow_matchers: Some(vec![FileMatcher::MimeType("application/x-cpio".to_owned())]),
+        keep_fast_matchers_if_accurate: true,
+        disabled_by_default: false
+    };
+}
+#[derive(Default, Clone)]
+pub struct CpioAdapter;
+
+impl CpioAdapter {
+    pub fn new() -> Self {
+        Self
+    }
+}
+impl GetMetadata for CpioAdapter {
+    fn metadata(&self) -> &AdapterMeta {
+        &METADATA
+    }
+}
+
+const S_IFMT: u32 = 0o170000;
+const S_IFREG: u32 = 0o100000;
+
+fn read_entries(ai: AdaptInfo, entries: &EntrySender) -> Result<()> {
+    let mut inp = SyncIoBridge::new(ai.inp);
+    loop {
+        let mut reader = NewcReader::new(inp).context("reading cpio header")?;
+        let entry = reader.entry();
+        if entry.is_trailer() {
+            break;
+        }
+        if entry.mode() & S_IFMT == S_IFREG {
+            let path = PathBuf::from(entry.name());
+            debug!(
+                "{}{}|{}: {}",
+                ai.line_prefix,
+                ai.filepath_hint.display(),
+                path.display(),
+                print_bytes(entry.file_size() as f64)
+            );
+            entries.send(path, &mut reader)?;
+        }
+        inp = reader.finish()?;
+    }
+    Ok(())
+}
+
+#[async_trait]
+impl FileAdapter for CpioAdapter {
+    async fn adapt(
+        &self,
+        ai: AdaptInfo,
+        _detection_reason: &FileMatcher,
+    ) -> Result<AdaptedFilesIterBox> {
+        Ok(blocking_entries(ai, read_entries))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{preproc::loop_adapt, test_utils::*};
+    use pretty_assertions::assert_eq;
+    use tokio::fs::File;
+
+    #[tokio::test]
+    async fn simple() -> Result<()> {
+        let filepath = test_data_dir().join("hello.cpio");
+        let (a, d) = simple_adapt_info(&filepath, Box::pin(File::open(&filepath).await?));
+        let o = adapted_to_vec(loop_adapt(&CpioAdapter::new(), d, a).await?).await?;
+        assert_eq!(
+            String::from_utf8(o)?,
+            "PREFIX:./etc/hello.conf: greeting = hello world
+PREFIX:./etc/hello.conf: 
+PREFIX:./usr/share/licenses/hello/LICENSE: License: GPL-3.0-or-later
+PREFIX:./usr/share/licenses/hello/LICENSE: 
+"
+        );
+        Ok(())
+    }
+}
@@ -1,3 +1,4 @@
+use super::blocking::SpooledFile;
 use super::*;
 use super::{AdaptInfo, AdapterMeta, FileAdapter, GetMetadata};
 use crate::adapted_iter::one_file;
@@ -59,8 +60,10 @@ pub struct CustomAdapterConfig {
     /// - `$input_file_stem`: the file name without the last extension. e.g. foo.tar.gz -> foo.tar
     /// - `$input_virtual_path`: the full input file path.
     ///   Note that this path may not actually exist on disk because it is the result of another adapter.
+    /// - `$input_file_path`: a path to the input file that exists on disk.
+    ///   If the input is the result of another adapter, it is first written to a temporary file.
     ///
-    /// stdin of the program will be connected to the input file, and stdout is assumed to be the converted file
+    /// stdin of the program will be connected to the input file (unless `$input_file_path` is used), and stdout is assumed to be the converted file
     pub args: Vec<String>,
 
     /// The output path hint.
@@ -116,8 +119,9 @@ lazy_static! {
         CustomAdapterConfig {
             name: "pandoc".to_string(),
             description: "Uses pandoc to convert binary/unreadable text documents to plain markdown-like text".to_string(),
-            version: 3,
-            extensions: strs(&["epub", "odt", "docx", "fb2", "ipynb", "html", "htm"]),
+            version: 5,
+            // html, ipynb and e-books are handled by the native html, notebook and ebook adapters
+            extensions: strs(&["epub", "fb2"]),
             binary: "pandoc".to_string(),
             mimetypes: None,
             // simpler markdown (with more information loss but plainer text)
@@ -128,7 +132,8 @@ lazy_static! {
                 "--wrap=none",
                 "--markdown-headings=atx"
             ]),
-            disabled_by_default: None,
+            // can still be enabled with --rga-adapters=+pandoc, which prefers it to the ebook adapter
+            disabled_by_default: Some(true),
             match_only_by_mime: None,
             output_path_hint: None
         },
@@ -146,6 +151,23 @@ lazy_static! {
             disabled_by_default: None,
             match_only_by_mime: None,
             output_path_hint: Some("${input_virtual_path}.txt.asciipagebreaks".into())
+        },
+        CustomAdapterConfig {
+            name: "djvutxt".to_owned(),
+            version: 2,
+            description: "Uses djvutxt (from djvulibre) to extract the hidden text layer of DjVu documents"
+                .to_owned(),
+
+            extensions: strs(&["djvu", "djv"]),
+            mimetypes: Some(strs(&["image/vnd.djvu", "image/x-djvu"])),
+
+            binary: "djvutxt".to_string(),
+            // djvutxt can't read from stdin.
+            // pages are separated by form feeds, which postprocpagebreaks turns into page numbers
+            args: strs(&["${input_file_path}"]),
+            disabled_by_default: None,
+            match_only_by_mime: None,
+            output_path_hint: Some("${input_virtual_path}.txt.asciipagebreaks".into())
         }
     ];
 }
@@ -198,6 +220,30 @@ pub fn pipe_output(
     )))
 }
 
+/// Runs a program that reads its input from a file instead of stdin
+fn file_output(
+    mut cmd: Command,
+    input: SpooledFile,
+    exe_name: &str,
+    help: &str,
+) -> Result<ReadBox> {
+    let cmd_log = format!("{:?}", cmd);
+    let mut cmd = cmd
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .spawn()
+        .map_err(|e| map_exe_error(e, exe_name, help))?;
+    let stdo = cmd.stdout.take().expect("is piped");
+    // the temporary input file is deleted once the program has exited
+    let cleanup = StreamReader::new(stream! {
+        drop(input);
+        yield std::io::Result::Ok(Bytes::new());
+    });
+    Ok(Box::pin(stdo.chain(
+        proc_wait(cmd, move || format!("subprocess: {cmd_log}")).chain(cleanup),
+    )))
+}
+
 pub struct CustomSpawningFileAdapter {
     binary: String,
     args: Vec<String>,
@@ -209,9 +255,12 @@ impl GetMetadata for CustomSpawningFileAdapter {
         &self.meta
     }
 }
-fn arg_replacer(arg: &str, filepath_hint: &Path) -> Result<String> {
+fn arg_replacer(arg: &str, filepath_hint: &Path, input_file: Option<&Path>) -> Result<String> {
     expand_str_ez(arg, |s| match s {
         "input_virtual_path" => Ok(filepath_hint.to_string_lossy()),
+        "input_file_path" => input_file
+            .map(Path::to_string_lossy)
+            .ok_or_else(|| anyhow::format_err!("${{input_file_path}} can only be used in args")),
         "input_file_stem" => Ok(filepath_hint
             .file_stem()
             .unwrap_or_default()
@@ -227,17 +276,24 @@ impl CustomSpawningFileAdapter {
     fn command(
         &self,
         filepath_hint: &std::path::Path,
+        input_file: Option<&std::path::Path>,
         mut command: tokio::process::Command,
     ) -> Result<tokio::process::Command> {
         command.args(
             self.args
                 .iter()
-                .map(|arg| arg_replacer(arg, filepath_hint))
+                .map(|arg| arg_replacer(arg, filepath_hint, input_file))
                 .collect::<Result<Vec<_>>>()?,
         );
         log::debug!("running command {:?}", command);
         Ok(command)
     }
+
+    fn reads_input_file(&self) -> bool {
+        self.args
+            .iter()
+            .any(|arg| arg.contains("$input_file_path") || arg.contains("${input_file_path}"))
+    }
 }
 #[async_trait]
 impl FileAdapter for CustomSpawningFileAdapter {
@@ -253,21 +309,34 @@ impl FileAdapter for CustomSpawningFileAdapter {
             archive_recursion_depth,
             postprocess,
             config,
-            ..
+            is_real_file,
         } = ai;
 
         let cmd = Command::new(&self.binary);
-        let cmd = self
-            .command(&filepath_hint, cmd)
-            .with_context(|| format!("Could not set cmd arguments for {}", self.binary))?;
-        debug!("executing {:?}", cmd);
-        let output = pipe_output(&line_prefix, cmd, inp, &self.binary, "")?;
+        let output = if self.reads_input_file() {
+            let hint = filepath_hint.clone();
+            let input =
+                tokio::task::spawn_blocking(move || SpooledFile::new(&hint, is_real_file, inp))
+                    .await??;
+            let cmd = self
+                .command(&filepath_hint, Some(input.path()), cmd)
+                .with_context(|| format!("Could not set cmd arguments for {}", self.binary))?;
+            debug!("executing {:?}", cmd);
+            file_output(cmd, input, &self.binary, "")?
+        } else {
+            let cmd = self
+                .command(&filepath_hint, None, cmd)
+                .with_context(|| format!("Could not set cmd arguments for {}", self.binary))?;
+            debug!("executing {:?}", cmd);
+            pipe_output(&line_prefix, cmd, inp, &self.binary, "")?
+        };
         Ok(one_file(AdaptInfo {
             filepath_hint: PathBuf::from(arg_replacer(
                 self.output_path_hint
                     .as_deref()
                     .unwrap_or("${input_virtual_path}.txt"),
                 &filepath_hint,
+                None,
             )?),
             inp: output,
             line_prefix,
@@ -324,6 +393,10 @@ mod test {
 
     #[tokio::test]
     async fn poppler() -> Result<()> {
+        if !crate::adapters::pdf::pdftotext_found() {
+            eprintln!("pdftotext is not installed, skipping");
+            return Ok(());
+        }
         let adapter = poppler_adapter();
 
         let filepath = test_data_dir().join("short.pdf");
@@ -345,6 +418,57 @@ PREFIX:Page 1:
         Ok(())
     }
 
+    #[tokio::test]
+    async fn djvutxt() -> Result<()> {
+        if std::process::Command::new("djvutxt").output().is_err() {
+            eprintln!("djvutxt is not installed, skipping");
+            return Ok(());
+        }
+        let adapter = BUILTIN_SPAWNING_ADAPTERS
+            .iter()
+            .find(|a| a.name == "djvutxt")
+            .unwrap()
+            .to_adapter();
+
+        let filepath = test_data_dir().join("../test.djvu");
+
+        let (a, d) = simple_adapt_info(&filepath, Box::pin(File::open(&filepath).await?));
+        let r = loop_adapt(&adapter, d, a).await?;
+        let o = String::from_utf8(adapted_to_vec(r).await?)?;
+        assert!(o.starts_with("PREFIX:Page 1: "), "{o:?}");
+        assert!(o.contains("\nPREFIX:Page 2: "), "{o:?}");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn djvutxt_page_breaks() -> Result<()> {
+        // cat stands in for djvutxt, which separates pages with form feeds. The input isn't a
+        // real file, so it has to be written to a temporary file first
+        let djvutxt = BUILTIN_SPAWNING_ADAPTERS
+            .iter()
+            .find(|a| a.name == "djvutxt")
+            .unwrap();
+        let adapter = CustomAdapterConfig {
+            binary: "cat".to_string(),
+            ..djvutxt.clone()
+        }
+        .to_adapter();
+        let (a, d) = simple_adapt_info(
+            Path::new("foo.djvu"),
+            Box::pin(Cursor::new(b"first page\n\x0csecond page\n\x0c".to_vec())),
+        );
+        let o = adapted_to_vec(loop_adapt(&adapter, d, a).await?).await?;
+        assert_eq!(
+            String::from_utf8(o)?,
+            "PREFIX:Page 1: first page
+PREFIX:Page 1: 
+PREFIX:Page 2: second page
+PREFIX:Page 2: 
+"
+        );
+        Ok(())
+    }
+
     use crate::{
         adapters::custom::CustomAdapterConfig,
         test_utils::{adapted_to_vec, simple_adapt_info},
@@ -8,10 +8,13 @@ use tokio::io::BufReader;
 
 use std::path::{Path, PathBuf};
 
-static EXTENSIONS: &[&str] = &["als", "bz2", "gz", "tbz", "tbz2", "tgz", "xz", "zst"];
+static EXTENSIONS: &[&str] = &[
+    "als", "bz2", "gz", "lzma", "tbz", "tbz2", "tgz", "xz", "zst",
+];
 static MIME_TYPES: &[&str] = &[
     "application/gzip",
     "application/x-bzip",
+    "application/x-lzma",
     "application/x-xz",
     "application/zstd",
 ];
@@ -59,6 +62,7 @@ fn decompress_any(reason: &FileMatcher, inp: ReadBox) -> Result<ReadBox> {
     let bz2 = |inp: ReadBox| Box::pin(bufread::BzDecoder::new(BufReader::new(inp)));
     let xz = |inp: ReadBox| Box::pin(bufread::XzDecoder::new(BufReader::new(inp)));
     let zst = |inp: ReadBox| Box::pin(bufread::ZstdDecoder::new(BufReader::new(inp)));
+    let lzma = |inp: ReadBox| Box::pin(bufread::LzmaDecoder::new(BufReader::new(inp)));
 
     Ok(match reason {
         Fast(FileExtension(ext)) => match ext.as_ref() {
@@ -66,6 +70,7 @@ fn decompress_any(reason: &FileMatcher, inp: ReadBox) -> Result<ReadBox> {
             "bz2" | "tbz" | "tbz2" => bz2(inp),
             "zst" => zst(inp),
             "xz" => xz(inp),
+            "lzma" => lzma(inp),
             ext => Err(format_err!("don't know how to decompress {}", ext))?,
         },
         MimeType(mime) => match mime.as_ref() {
@@ -73,6 +78,7 @@ fn decompress_any(reason: &FileMatcher, inp: ReadBox) -> Result<ReadBox> {
             "application/x-bzip" => bz2(inp),
             "application/x-xz" => xz(inp),
             "application/zstd" => zst(inp),
+            "application/x-lzma" => lzma(inp),
             mime => Err(format_err!("don't know how to decompress mime {}", mime))?,
         },
     })
@@ -157,16 +163,7 @@ mod tests {
         let (a, d) = simple_adapt_info(&filepath, Box::pin(File::open(&filepath).await?));
         let r = loop_adapt(&adapter, d, a).await?;
         let o = adapted_to_vec(r).await?;
-        assert_eq!(
-            String::from_utf8(o)?,
-            "PREFIX:Page 1: hello world
-PREFIX:Page 1: this is just a test.
-PREFIX:Page 1: 
-PREFIX:Page 1: 1
-PREFIX:Page 1: 
-PREFIX:Page 1: 
-"
-        );
+        assert_eq!(String::from_utf8(o)?, short_pdf_text("PREFIX:"));
         Ok(())
     }
 }
@@ -0,0 +1,803 @@
+use super::blocking::{EntrySender, SpooledFile, blocking_entries};
+use super::xml::{attribute, event_text, resolve_target, write_lines};
+use super::*;
+use ::zip::ZipArchive;
+use ::zip::result::ZipError;
+use anyhow::{Result, bail};
+use lazy_static::lazy_static;
+use quick_xml::Reader;
+use quick_xml::events::Event;
+use regex::Regex;
+use regex::bytes::Regex as BytesRegex;
+use std::collections::{HashMap, HashSet};
+use std::io::{Read, Seek};
+use std::path::Path;
+
+static EXTENSIONS: &[&str] = &["epub", "mobi", "azw", "azw3", "fb2"];
+static MIME_TYPES: &[&str] = &[
+    "application/epub+zip",
+    "application/x-mobipocket-ebook",
+    "application/vnd.amazon.ebook",
+    "application/x-fictionbook+xml",
+];
+
+lazy_static! {
+    static ref METADATA: AdapterMeta = AdapterMeta {
+        name: "ebook".to_owned(),
+        version: 1,
+        description: "Reads EPUB, MOBI/AZW3 and FictionBook e-books chapter by chapter, prefixing each line with its chapter title (or `#3` for the third chapter if it has none)".to_owned(),
+        recurses: true,
+        fast_matchers: EXTENSIONS
+            .iter()
+            .map(|s| FastFileMatcher::FileExtension(s.to_string()))
+            .collect(),
+        slow_matchers: Some(
+            MIME_TYPES
+                .iter()
+                .map(|s| FileMatcher::MimeType(s.to_string()))
+                .collect()
+        ),
+        keep_fast_matchers_if_accurate: true,
+        disabled_by_default: false
+    };
+    /// Chapter boundaries in MOBI markup: page breaks, or the start of the next file in KF8 (AZW3) books
+    static ref MOBI_CHAPTER: BytesRegex =
+        BytesRegex::new(r"(?i)<mbp:pagebreak[^>]*>|<html[\s>]").unwrap();
+    static ref MOBI_GUIDE_TOC: BytesRegex =
+        BytesRegex::new(r#"(?i)<reference\s[^>]*type=["']?toc[^>]*>"#).unwrap();
+    static ref FILEPOS: BytesRegex = BytesRegex::new(r#"(?i)filepos=["']?(\d+)"#).unwrap();
+    static ref FILEPOS_LINK: BytesRegex =
+        BytesRegex::new(r#"(?is)<a\s[^>]*filepos=["']?(\d+)[^>]*>(.*?)</a>"#).unwrap();
+    static ref HEADING: BytesRegex = BytesRegex::new(r"(?is)<h[1-3][^>]*>(.*?)</h[1-3]>").unwrap();
+    static ref TAG: Regex = Regex::new(r"<[^>]*>").unwrap();
+    static ref XML_ENCODING: Regex =
+        Regex::new(r#"^<\?xml[^>]*encoding=["']([A-Za-z0-9._:-]+)["']"#).unwrap();
+}
+
+#[derive(Default, Clone)]
+pub struct EbookAdapter;
+
+impl EbookAdapter {
+    pub fn new() -> Self {
+        Self
+    }
+}
+impl GetMetadata for EbookAdapter {
+    fn metadata(&self) -> &AdapterMeta {
+        &METADATA
+    }
+}
+
+/// Collapses the whitespace of a chapter title
+fn normalize_title(title: &str) -> Option<String> {
+    let title = title.split_whitespace().collect::<Vec<_>>().join(" ");
+    (!title.is_empty()).then_some(title)
+}
+
+/// Decodes `%20` style escapes in the links of an EPUB
+fn percent_decode(href: &str) -> String {
+    let bytes = href.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|h| std::str::from_utf8(h).ok());
+        match hex.and_then(|h| u8::from_str_radix(h, 16).ok()) {
+            Some(byte) if bytes[i] == b'%' => {
+                decoded.push(byte);
+                i += 3;
+            }
+            _ => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+type Book = ZipArchive<std::fs::File>;
+
+fn read_part(book: &mut Book, part: &str) -> Result<Option<Vec<u8>>> {
+    match book.by_name(part) {
+        Ok(mut file) => {
+            let mut data = Vec::new();
+            file.read_to_end(&mut data)?;
+            Ok(Some(data))
+        }
+        Err(ZipError::FileNotFound) => Ok(None),
+        Err(e) => Err(e).with_context(|| format!("reading {part}")),
+    }
+}
+
+struct ManifestItem {
+    /// the name of the item within the zip file
+    part: String,
+    media_type: String,
+    properties: String,
+}
+
+/// The manifest and reading order of an EPUB, from its OPF package document
+struct Package {
+    manifest: HashMap<String, ManifestItem>,
+    spine: Vec<String>,
+    /// the manifest id of the EPUB 2 table of contents
+    ncx: Option<String>,
+}
+
+fn read_package(data: &[u8], opf_part: &str) -> Result<Package> {
+    let mut package = Package {
+        manifest: HashMap::new(),
+        spine: Vec::new(),
+        ncx: None,
+    };
+    let mut reader = Reader::from_reader(data);
+    let mut buf = Vec::new();
+    loop {
+        match reader.read_event_into(&mut buf)? {
+            Event::Start(e) | Event::Empty(e) => match e.local_name().as_ref() {
+                b"item" => {
+                    let (Some(id), Some(href)) = (attribute(&e, b"id")?, attribute(&e, b"href")?)
+                    else {
+                        continue;
+                    };
+                    package.manifest.insert(
+                        id,
+                        ManifestItem {
+                            part: resolve_target(opf_part, &percent_decode(&href)),
+                            media_type: attribute(&e, b"media-type")?.unwrap_or_default(),
+                            properties: attribute(&e, b"properties")?.unwrap_or_default(),
+                        },
+                    );
+                }
+                b"spine" => package.ncx = attribute(&e, b"toc")?,
+                b"itemref" => package.spine.extend(attribute(&e, b"idref")?),
+                _ => {}
+            },
+            Event::Eof => break,
+            _ => {}
+        }
+        buf.clear();
+    }
+    Ok(package)
+}
+
+/// An entry of the table of contents of an EPUB
+struct TocEntry {
+    part: String,
+    /// the id of the element where the chapter starts, if it does not start with the part
+    fragment: Option<String>,
+    title: String,
+}
+
+impl TocEntry {
+    fn new(toc_part: &str, href: &str, title: &str) -> Option<Self> {
+        let (path, fragment) = match href.split_once('#') {
+            Some((path, fragment)) => (path, Some(percent_decode(fragment))),
+            None => (href, None),
+        };
+        Some(Self {
+            part: resolve_target(toc_part, &percent_decode(path)),
+            fragment,
+            title: normalize_title(title)?,
+        })
+    }
+}
+
+/// Reads the chapters from an EPUB 2 NCX file (`navPoint` elements)
+fn read_ncx(data: &[u8], ncx_part: &str) -> Result<Vec<TocEntry>> {
+    let mut entries = Vec::new();
+    let mut reader = Reader::from_reader(data);
+    let mut buf = Vec::new();
+    let mut label = String::new();
+    let mut in_label = false;
+    loop {
+        let event = reader.read_event_into(&mut buf)?;
+        match &event {
+            Event::Start(e) if e.local_name().as_ref() == b"text" => {
+                in_label = true;
+                label.clear();
+            }
+            Event::End(e) if e.local_name().as_ref() == b"text" => in_label = false,
+            Event::Start(e) | Event::Empty(e) if e.local_name().as_ref() == b"content" => {
+                if let Some(src) = attribute(e, b"src")? {
+                    entries.extend(TocEntry::new(ncx_part, &src, &label));
+                }
+            }
+            Event::Eof => break,
+            event if in_label => label.push_str(&event_text(event)?.unwrap_or_default()),
+            _ => {}
+        }
+        buf.clear();
+    }
+    Ok(entries)
+}
+
+/// Reads the chapters from the `<nav epub:type="toc">` element of an EPUB 3 navigation document
+fn read_nav(data: &[u8], nav_part: &str) -> Result<Vec<TocEntry>> {
+    let mut entries = Vec::new();
+    let mut reader = Reader::from_reader(data);
+    let mut buf = Vec::new();
+    // the nesting depth of nav elements since the table of contents started
+    let mut toc_depth = 0;
+    let mut link: Option<(String, String)> = None;
+    loop {
+        let event = reader.read_event_into(&mut buf)?;
+        match &event {
+            Event::Start(e) if e.local_name().as_ref() == b"nav" => {
+                let is_toc = attribute(e, b"type")?
+                    .is_some_and(|t| t.split_whitespace().any(|t| t == "toc"));
+                if toc_depth > 0 || is_toc {
+                    toc_depth += 1;
+                }
+            }
+            Event::End(e) if e.local_name().as_ref() == b"nav" && toc_depth > 0 => toc_depth -= 1,
+            Event::Start(e) if toc_depth > 0 && e.local_name().as_ref() == b"a" => {
+                link = attribute(e, b"href")?.map(|href| (href, String::new()));
+            }
+            Event::End(e) if e.local_name().as_ref() == b"a" => {
+                if let Some((href, title)) = link.take() {
+                    entries.extend(TocEntry::new(nav_part, &href, &title));
+                }
+            }
+            Event::Eof => break,
+            event => {
+                if let Some((_, title)) = &mut link {
+                    title.push_str(&event_text(event)?.unwrap_or_default());
+                }
+            }
+        }
+        buf.clear();
+    }
+    Ok(entries)
+}
+
+/// Returns the names of the parts that are encrypted (by DRM, or obfuscated fonts)
+fn encrypted_parts(book: &mut Book) -> Result<HashSet<String>> {
+    let mut parts = HashSet::new();
+    let Some(data) = read_part(book, "META-INF/encryption.xml")? else {
+        return Ok(parts);
+    };
+    let mut reader = Reader::from_reader(&data[..]);
+    let mut buf = Vec::new();
+    loop {
+        match reader.read_event_into(&mut buf)? {
+            Event::Start(e) | Event::Empty(e) if e.local_name().as_ref() == b"CipherReference" => {
+                // relative to the root of the container
+                parts.extend(attribute(&e, b"URI")?.map(|uri| percent_decode(&uri)));
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+        buf.clear();
+    }
+    Ok(parts)
+}
+
+/// Finds the start of the tag with the given id in an (x)html document
+fn find_anchor(html: &[u8], id: &str) -> Option<usize> {
+    let id_attribute =
+        regex::bytes::Regex::new(&format!(r#"\sid\s*=\s*["']{}["']"#, regex::escape(id))).ok()?;
+    let attribute_start = id_attribute.find(html)?.start();
+    html[..attribute_start].iter().rposition(|&c| c == b'<')
+}
+
+fn read_epub(mut book: Book, entries: &EntrySender) -> Result<()> {
+    let container = read_part(&mut book, "META-INF/container.xml")?
+        .context("no META-INF/container.xml, not an EPUB file")?;
+    let mut reader = Reader::from_reader(&container[..]);
+    let mut buf = Vec::new();
+    let opf_part = loop {
+        match reader.read_event_into(&mut buf)? {
+            Event::Start(e) | Event::Empty(e) if e.local_name().as_ref() == b"rootfile" => {
+                if let Some(path) = attribute(&e, b"full-path")? {
+                    break path;
+                }
+            }
+            Event::Eof => bail!("no rootfile in META-INF/container.xml"),
+            _ => {}
+        }
+        buf.clear();
+    };
+    let opf = read_part(&mut book, &opf_part)?.with_context(|| format!("missing {opf_part}"))?;
+    let package = read_package(&opf, &opf_part).with_context(|| format!("parsing {opf_part}"))?;
+    // the EPUB 3 navigation document, or the NCX file of EPUB 2
+    let toc_item = package
+        .manifest
+        .values()
+        .find(|item| item.properties.split_whitespace().any(|p| p == "nav"))
+        .map(|item| (item, true))
+        .or_else(|| {
+            let ncx = package.ncx.as_ref().and_then(|id| package.manifest.get(id));
+            let ncx = ncx.or_else(|| {
+                package
+                    .manifest
+                    .values()
+                    .find(|item| item.media_type == "application/x-dtbncx+xml")
+            });
+            ncx.map(|item| (item, false))
+        });
+    let mut toc = Vec::new();
+    if let Some((item, is_nav)) = toc_item
+        && let Some(data) = read_part(&mut book, &item.part)?
+    {
+        let entries = if is_nav {
+            read_nav(&data, &item.part)
+        } else {
+            read_ncx(&data, &item.part)
+        };
+        // the chapters are still extracted without titles
+        toc = entries.unwrap_or_else(|e| {
+            debug!("could not read table of contents {}: {e:#}", item.part);
+            Vec::new()
+        });
+    }
+    let encrypted = encrypted_parts(&mut book)?;
+
+    // chapters that span several files keep the title of the chapter they started in
+    let mut title: Option<String> = None;
+    for (i, idref) in package.spine.iter().enumerate() {
+        let Some(item) = package.manifest.get(idref) else {
+            continue;
+        };
+        let fallback_label = format!("#{}", i + 1);
+        if encrypted.contains(&item.part) {
+            entries.send_message(Path::new(&fallback_label), "chapter is encrypted")?;
+            continue;
+        }
+        let Some(content) = read_part(&mut book, &item.part)? else {
+            continue;
+        };
+        let filepath_hint = match Path::new(&item.part).extension().and_then(|e| e.to_str()) {
+            Some("html" | "htm" | "xhtml") => item.part.clone(),
+            _ if item.media_type.contains("html") => format!("{}.html", item.part),
+            _ => item.part.clone(),
+        };
+        // the chapters that start within this file, by position
+        let mut starts: Vec<(usize, &str)> = toc
+            .iter()
+            .filter(|entry| entry.part == item.part)
+            .filter_map(|entry| match &entry.fragment {
+                Some(fragment) => Some((find_anchor(&content, fragment)?, entry.title.as_str())),
+                None => Some((0, entry.title.as_str())),
+            })
+            .collect();
+        starts.sort_by_key(|(start, _)| *start);
+        starts.dedup_by_key(|(start, _)| *start);
+        if starts.first().is_none_or(|(start, _)| *start > 0) {
+            starts.insert(0, (0, ""));
+        }
+        for (j, (start, chapter_title)) in starts.iter().enumerate() {
+            if !chapter_title.is_empty() {
+                title = Some(chapter_title.to_string());
+            }
+            let end = starts.get(j + 1).map_or(content.len(), |(end, _)| *end);
+            entries.send_as(
+                title
+                    .clone()
+                    .unwrap_or_else(|| fallback_label.clone())
+                    .into(),
+                filepath_hint.clone().into(),
+                &content[*start..end],
+            )?;
+        }
+    }
+    Ok(())
+}
+
+fn u16_be(data: &[u8], pos: usize) -> Option<u16> {
+    Some(u16::from_be_bytes(data.get(pos..pos + 2)?.try_into().ok()?))
+}
+
+fn u32_be(data: &[u8], pos: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(data.get(pos..pos + 4)?.try_into().ok()?))
+}
+
+/// Decompresses a text record with the LZ77 variant of PalmDOC
+fn palmdoc_decompress(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(4096);
+    let mut i = 0;
+    while let Some(&c) = data.get(i) {
+        i += 1;
+        match c {
+            // the next 1-8 bytes are literals
+            1..=8 => {
+                let end = (i + c as usize).min(data.len());
+                out.extend_from_slice(&data[i..end]);
+                i = end;
+            }
+            // a distance and length pair referring to the decompressed text
+            0x80..=0xBF => {
+                let Some(&next) = data.get(i) else {
+                    break;
+                };
+                i += 1;
+                let pair = ((c as usize) << 8 | next as usize) & 0x3FFF;
+                let (distance, length) = (pair >> 3, (pair & 7) + 3);
+                if distance == 0 || distance > out.len() {
+                    break;
+                }
+                for _ in 0..length {
+                    out.push(out[out.len() - distance]);
+                }
+            }
+            // a space followed by a character
+            0xC0.. => out.extend_from_slice(&[b' ', c ^ 0x80]),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// Returns the size of the data MOBI appends to each text record, as flagged in its header
+fn trailing_entries_size(record: &[u8], flags: u16) -> usize {
+    let mut size = 0;
+    for bit in 1..16 {
+        if flags & (1 << bit) == 0 {
+            continue;
+        }
+        // the size is stored as a backwards variable-length integer at the end of the entry
+        let mut pos = record.len().saturating_sub(size);
+        let (mut value, mut shift) = (0, 0);
+        while pos > 0 {
+            pos -= 1;
+            value |= ((record[pos] & 0x7F) as usize) << shift;
+            shift += 7;
+            if record[pos] & 0x80 != 0 || shift >= 28 {
+                break;
+            }
+        }
+        size += value;
+    }
+    // multibyte characters that overlap into the next record
+    if flags & 1 != 0
+        && let Some(pos) = record.len().checked_sub(size + 1)
+    {
+        size += (record[pos] & 3) as usize + 1;
+    }
+    size
+}
+
+/// Reads the markup of a MOBI book from the text records of its PalmDB file, and returns it with its encoding
+fn mobi_text(data: &[u8]) -> Result<(Vec<u8>, &'static encoding_rs::Encoding)> {
+    let records = u16_be(data, 76).context("truncated PalmDB header")? as usize;
+    let offsets = (0..records)
+        .map(|i| u32_be(data, 78 + i * 8).map(|offset| offset as usize))
+        .collect::<Option<Vec<_>>>()
+        .context("truncated PalmDB record list")?;
+    let record = |i: usize| -> Option<&[u8]> {
+        let end = offsets.get(i + 1).copied().unwrap_or(data.len());
+        data.get(*offsets.get(i)?..end)
+    };
+    let header = record(0).context("missing MOBI header")?;
+    let compression = u16_be(header, 0).context("truncated MOBI header")?;
+    let text_records = u16_be(header, 8).context("truncated MOBI header")? as usize;
+    if u16_be(header, 12) != Some(0) {
+        bail!("the e-book is DRM protected");
+    }
+    let (encoding, flags) = if header.get(16..20) == Some(b"MOBI") {
+        let header_length = u32_be(header, 20).unwrap_or(0);
+        let flags = match header_length >= 0xE4 {
+            true => u16_be(header, 0xF2).unwrap_or(0),
+            false => 0,
+        };
+        (u32_be(header, 28).unwrap_or(1252), flags)
+    } else {
+        (1252, 0)
+    };
+    let mut text = Vec::new();
+    for i in 1..=text_records {
+        let record = record(i).with_context(|| format!("missing text record {i}"))?;
+        let record =
+            &record[..record.len() - trailing_entries_size(record, flags).min(record.len())];
+        match compression {
+            1 => text.extend_from_slice(record),
+            2 => text.extend(palmdoc_decompress(record)),
+            17480 => bail!("HUFF/CDIC compressed MOBI files are not supported"),
+            other => bail!("unknown MOBI compression {other}"),
+        }
+    }
+    let encoding = match encoding {
+        65001 => encoding_rs::UTF_8,
+        _ => encoding_rs::WINDOWS_1252,
+    };
+    Ok((text, encoding))
+}
+
+/// Returns the plain text of a bit of markup, e.g. a link or heading
+fn markup_title(markup: &[u8], encoding: &'static encoding_rs::Encoding) -> Option<String> {
+    let markup = encoding.decode_without_bom_handling(markup).0;
+    normalize_title(&html_escape::decode_html_entities(
+        &TAG.replace_all(&markup, " "),
+    ))
+}
+
+/// Reads the chapters from the table of contents the guide of a MOBI book points to.
+///
+/// Its links refer to byte offsets (`filepos`) in the text.
+fn mobi_toc(text: &[u8], encoding: &'static encoding_rs::Encoding) -> Vec<(usize, String)> {
+    let toc_start = MOBI_GUIDE_TOC
+        .find(text)
+        .and_then(|reference| FILEPOS.captures(reference.as_bytes()))
+        .and_then(|filepos| std::str::from_utf8(&filepos[1]).ok()?.parse::<usize>().ok())
+        .filter(|start| *start < text.len());
+    let Some(toc_start) = toc_start else {
+        return Vec::new();
+    };
+    let toc_end = MOBI_CHAPTER
+        .find_at(text, toc_start + 1)
+        .map_or(text.len(), |m| m.start());
+    let mut chapters: Vec<(usize, String)> = FILEPOS_LINK
+        .captures_iter(&text[toc_start..toc_end])
+        .filter_map(|link| {
+            let start = std::str::from_utf8(&link[1]).ok()?.parse().ok()?;
+            Some((start, markup_title(&link[2], encoding)?))
+        })
+        .filter(|(start, _)| *start < text.len())
+        .collect();
+    chapters.sort_by_key(|(start, _)| *start);
+    chapters.dedup_by_key(|(start, _)| *start);
+    chapters
+}
+
+/// Splits the text of a MOBI book at page breaks and files, titled by their first heading
+fn mobi_parts(text: &[u8], encoding: &'static encoding_rs::Encoding) -> Vec<(usize, String)> {
+    let mut starts = vec![0];
+    for m in MOBI_CHAPTER.find_iter(text) {
+        // the start of a new file is part of the next chapter
+        starts.push(match m.as_bytes().starts_with(b"<mbp") {
+            true => m.end(),
+            false => m.start(),
+        });
+    }
+    starts.dedup();
+    starts
+        .iter()
+        .enumerate()
+        .map(|(i, start)| {
+            let end = starts.get(i + 1).copied().unwrap_or(text.len());
+            let title = HEADING
+                .captures(&text[*start..end])
+                .and_then(|heading| markup_title(&heading[1], encoding));
+            (*start, title.unwrap_or_default())
+        })
+        .collect()
+}
+
+fn read_mobi(mut file: impl Read, entries: &EntrySender) -> Result<()> {
+    let mut data = Vec::new();
+    file.read_to_end(&mut data)?;
+    let (text, encoding) = mobi_text(&data)?;
+    let mut chapters = mobi_toc(&text, encoding);
+    if chapters.is_empty() {
+        chapters = mobi_parts(&text, encoding);
+    }
+    if chapters.first().is_none_or(|(start, _)| *start > 0) {
+        chapters.insert(0, (0, String::new()));
+    }
+    for (i, (start, title)) in chapters.iter().enumerate() {
+        let end = chapters.get(i + 1).map_or(text.len(), |(end, _)| *end);
+        let chapter = encoding.decode_without_bom_handling(&text[*start..end]).0;
+        if TAG.replace_all(&chapter, "").trim().is_empty() {
+            continue;
+        }
+        let label = match title.is_empty() {
+            true => format!("#{}", i + 1),
+            false => title.clone(),
+        };
+        entries.send_as(
+            label.into(),
+            format!("part{}.html", i + 1).into(),
+            chapter.as_bytes(),
+        )?;
+    }
+    Ok(())
+}
+
+/// Decodes an XML document in the encoding given by its declaration, e.g. windows-1251 in older FictionBooks
+fn decode_xml(data: &[u8]) -> String {
+    let declaration = String::from_utf8_lossy(&data[..data.len().min(200)]);
+    let encoding = XML_ENCODING
+        .captures(declaration.trim_start_matches('\u{feff}'))
+        .and_then(|c| encoding_rs::Encoding::for_label(c[1].as_bytes()))
+        .unwrap_or(encoding_rs::UTF_8);
+    encoding.decode(data).0.into_owned()
+}
+
+/// Reads the sections of a FictionBook, sending each one with the title of its (innermost titled) section
+fn read_fb2(mut file: impl Read, entries: &EntrySender) -> Result<()> {
+    let mut data = Vec::new();
+    file.read_to_end(&mut data)?;
+    let text = decode_xml(&data);
+    let mut reader = Reader::from_str(&text);
+    // the label of each open section
+    let mut sections: Vec<String> = Vec::new();
+    let mut top_level_sections = 0;
+    // the label for text outside of sections, e.g. `notes`
+    let mut body_label = String::new();
+    // the text of the current title, which becomes the label of its section
+    let mut title: Option<String> = None;
+    // depth within the metadata and embedded images
+    let mut skip = 0;
+    let mut chapter = String::new();
+    let mut sent = 0;
+    let mut send = |label: &str, chapter: &mut String| -> Result<()> {
+        let mut lines = Vec::new();
+        write_lines(&mut lines, "", chapter)?;
+        if !lines.is_empty() {
+            sent += 1;
+            entries.send_as(
+                label.into(),
+                format!("chapter{sent}.txt").into(),
+                &lines[..],
+            )?;
+        }
+        chapter.clear();
+        Ok(())
+    };
+    loop {
+        let event = reader.read_event()?;
+        let label = sections.last().unwrap_or(&body_label).clone();
+        match &event {
+            Event::Start(e) => match e.local_name().as_ref() {
+                b"description" | b"binary" => skip += 1,
+                b"body" => body_label = attribute(e, b"name")?.unwrap_or_else(|| "body".into()),
+                b"section" => {
+                    send(&label, &mut chapter)?;
+                    if sections.is_empty() {
+                        top_level_sections += 1;
+                    }
+                    // untitled sections belong to the section they are in
+                    sections.push(match sections.last() {
+                        Some(parent) => parent.clone(),
+                        None => format!("#{top_level_sections}"),
+                    });
+                }
+                b"title" if !sections.is_empty() => title = Some(String::new()),
+                _ => {}
+            },
+            Event::End(e) => match e.local_name().as_ref() {
+                b"description" | b"binary" => skip -= 1,
+                b"section" => {
+                    send(&label, &mut chapter)?;
+                    sections.pop();
+                }
+                b"title" => {
+                    if let Some(section_title) = title.take().as_deref().and_then(normalize_title)
+                        && let Some(section) = sections.last_mut()
+                    {
+                        *section = section_title;
+                    }
+                }
+                b"p" | b"v" | b"subtitle" | b"text-author" | b"td" | b"th" => {
+                    chapter.push('\n');
+                    if let Some(title) = &mut title {
+                        title.push(' ');
+                    }
+                }
+                _ => {}
+            },
+            Event::Empty(e) if e.local_name().as_ref() == b"empty-line" => chapter.push('\n'),
+            Event::Eof => break,
+            event if skip == 0 => {
+                if let Some(text) = event_text(event)? {
+                    chapter.push_str(&text);
+                    if let Some(title) = &mut title {
+                        title.push_str(&text);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    let label = sections.last().unwrap_or(&body_label).clone();
+    send(&label, &mut chapter)
+}
+
+fn read_entries(ai: AdaptInfo, entries: &EntrySender) -> Result<()> {
+    // EPUBs are zip files, which have their index at the end
+    let file = SpooledFile::new(&ai.filepath_hint, ai.is_real_file, ai.inp)?;
+    let mut inp = file.open()?;
+    let mut magic = Vec::new();
+    (&mut inp).take(68).read_to_end(&mut magic)?;
+    inp.rewind()?;
+    if magic.starts_with(b"PK") {
+        let book = ZipArchive::new(inp)
+            .with_context(|| format!("opening {}", ai.filepath_hint.display()))?;
+        read_epub(book, entries)
+    } else if magic.get(60..68) == Some(b"BOOKMOBI") {
+        read_mobi(inp, entries)
+    } else {
+        read_fb2(inp, entries)
+    }
+}
+
+#[async_trait]
+impl FileAdapter for EbookAdapter {
+    async fn adapt(
+        &self,
+        ai: AdaptInfo,
+        _detection_reason: &FileMatcher,
+    ) -> Result<AdaptedFilesIterBox> {
+        Ok(blocking_entries(ai, read_entries))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use pretty_assertions::assert_eq;
+
+    /// Returns the chapter labels of the output in order
+    fn chapters(output: &str) -> Vec<&str> {
+        let mut chapters: Vec<&str> = output
+            .lines()
+            .filter_map(|line| line.strip_prefix("PREFIX:")?.split_once(": "))
+            .map(|(chapter, _)| chapter)
+            .collect();
+        chapters.dedup();
+        chapters
+    }
+
+    const WASTELAND_CHAPTERS: &[&str] = &[
+        "I. THE BURIAL OF THE DEAD",
+        "II. A GAME OF CHESS",
+        "III. THE FIRE SERMON",
+        "IV. DEATH BY WATER",
+        "V. WHAT THE THUNDER SAID",
+        "NOTES ON \"THE WASTE LAND\"",
+    ];
+
+    #[tokio::test]
+    async fn epub() -> Result<()> {
+        // all chapters are in one file, the table of contents links to their ids
+        let output = adapt_test_file(&EbookAdapter::new(), "../wasteland.epub", true).await?;
+        assert_eq!(chapters(&output)[0], "#1");
+        assert_eq!(chapters(&output)[1..], *WASTELAND_CHAPTERS);
+        assert!(
+            output.contains(
+                "\nPREFIX:IV. DEATH BY WATER: Phlebas the Phoenician, a fortnight dead,\n"
+            )
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn mobi() -> Result<()> {
+        let output = adapt_test_file(&EbookAdapter::new(), "../wasteland.mobi", true).await?;
+        assert_eq!(chapters(&output)[0], "#1");
+        assert_eq!(chapters(&output)[1..], *WASTELAND_CHAPTERS);
+        assert!(
+            output.contains(
+                "\nPREFIX:IV. DEATH BY WATER: Phlebas the Phoenician, a fortnight dead,\n"
+            )
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn fb2() -> Result<()> {
+        let output = adapt_test_file(&EbookAdapter::new(), "../wasteland.fb2", true).await?;
+        assert!(output.starts_with(
+            "PREFIX:body: The Waste Land\nPREFIX:body: \nPREFIX:I. THE BURIAL OF THE DEAD: I. THE BURIAL OF THE DEAD\n"
+        ));
+        // the notes are sections of their own body, titled by their number
+        assert_eq!(chapters(&output)[1..7], *WASTELAND_CHAPTERS);
+        assert!(
+            output.contains(
+                "\nPREFIX:IV. DEATH BY WATER: Phlebas the Phoenician, a fortnight dead,\n"
+            )
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn palmdoc() {
+        assert_eq!(
+            palmdoc_decompress(b"ab\x02\x01\x80\x80\x22\xe8c"),
+            b"ab\x01\x80ab\x01\x80a hc"
+        );
+    }
+}
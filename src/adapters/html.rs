@@ -0,0 +1,237 @@
+use super::writing::WritingFileAdapter;
+use super::*;
+use anyhow::Result;
+use lazy_static::lazy_static;
+use lol_html::html_content::{Element, TextType};
+use lol_html::{HtmlRewriter, Settings, doc_text, element, end_tag};
+use std::cell::RefCell;
+use std::io::{BufWriter, Read, Write};
+use std::rc::Rc;
+use tokio::io::AsyncWrite;
+use tokio_util::io::SyncIoBridge;
+
+static EXTENSIONS: &[&str] = &["html", "htm", "xhtml"];
+static MIME_TYPES: &[&str] = &["text/html", "application/xhtml+xml"];
+/// Elements that start and end on their own line
+const BLOCKS: &str = "address, article, aside, blockquote, br, dd, details, div, dl, dt, fieldset, figcaption, figure, footer, form, h1, h2, h3, h4, h5, h6, header, hr, li, main, nav, ol, p, pre, section, summary, table, title, tr, ul";
+
+lazy_static! {
+    static ref METADATA: AdapterMeta = AdapterMeta {
+        name: "html".to_owned(),
+        version: 1,
+        description:
+            "Converts html to plain text without scripts and styles, keeping the targets of links"
+                .to_owned(),
+        recurses: false,
+        fast_matchers: EXTENSIONS
+            .iter()
+            .map(|s| FastFileMatcher::FileExtension(s.to_string()))
+            .collect(),
+        slow_matchers: Some(
+            MIME_TYPES
+                .iter()
+                .map(|s| FileMatcher::MimeType(s.to_string()))
+                .collect()
+        ),
+        keep_fast_matchers_if_accurate: true,
+        disabled_by_default: false
+    };
+}
+
+#[derive(Default, Clone)]
+pub struct HtmlAdapter;
+
+impl HtmlAdapter {
+    pub fn new() -> Self {
+        Self
+    }
+}
+impl GetMetadata for HtmlAdapter {
+    fn metadata(&self) -> &AdapterMeta {
+        &METADATA
+    }
+}
+
+/// Writes text with its whitespace collapsed the way a browser would render it
+struct TextWriter<W: Write> {
+    out: W,
+    /// the current text node, which can be split over several chunks of the input
+    text: String,
+    pending_space: bool,
+    line_start: bool,
+    pre_depth: usize,
+}
+
+impl<W: Write> TextWriter<W> {
+    fn new(out: W) -> Self {
+        Self {
+            out,
+            text: String::new(),
+            pending_space: false,
+            line_start: true,
+            pre_depth: 0,
+        }
+    }
+
+    fn write_text(&mut self, text: &str) -> std::io::Result<()> {
+        if self.pre_depth > 0 {
+            self.out.write_all(text.as_bytes())?;
+            self.line_start = text.ends_with('\n');
+            self.pending_space = false;
+            return Ok(());
+        }
+        if text.starts_with(char::is_whitespace) {
+            self.pending_space = true;
+        }
+        for word in text.split_whitespace() {
+            if self.pending_space && !self.line_start {
+                self.out.write_all(b" ")?;
+            }
+            self.out.write_all(word.as_bytes())?;
+            self.line_start = false;
+            self.pending_space = true;
+        }
+        self.pending_space = text.ends_with(char::is_whitespace);
+        Ok(())
+    }
+
+    fn space(&mut self) {
+        self.pending_space = true;
+    }
+
+    fn newline(&mut self) -> std::io::Result<()> {
+        if !self.line_start {
+            self.out.write_all(b"\n")?;
+            self.line_start = true;
+        }
+        self.pending_space = false;
+        Ok(())
+    }
+}
+
+/// Calls `f` now and again at the end tag of the element, if it has one
+fn at_start_and_end<W: Write + 'static>(
+    el: &mut Element,
+    text: &Rc<RefCell<TextWriter<W>>>,
+    f: fn(&mut TextWriter<W>) -> std::io::Result<()>,
+) -> lol_html::HandlerResult {
+    f(&mut text.borrow_mut())?;
+    if el.can_have_content() {
+        let text = text.clone();
+        el.on_end_tag(end_tag!(move |_| Ok(f(&mut text.borrow_mut())?)))?;
+    }
+    Ok(())
+}
+
+fn synchronous_html_to_text(ai: AdaptInfo, out: impl Write + 'static) -> Result<()> {
+    let text = Rc::new(RefCell::new(TextWriter::new(BufWriter::new(out))));
+    let mut rewriter = HtmlRewriter::new(
+        Settings {
+            element_content_handlers: vec![
+                element!(BLOCKS, |el| at_start_and_end(
+                    el,
+                    &text,
+                    TextWriter::newline
+                )),
+                element!("td, th", |_| {
+                    text.borrow_mut().space();
+                    Ok(())
+                }),
+                element!("pre", |el| {
+                    text.borrow_mut().pre_depth += 1;
+                    let text = text.clone();
+                    el.on_end_tag(end_tag!(move |_| {
+                        text.borrow_mut().pre_depth -= 1;
+                        Ok(())
+                    }))
+                }),
+                element!("a[href]", |el| {
+                    let href = el.get_attribute("href").unwrap_or_default();
+                    // anchors within the page and scripts are not useful to search for
+                    if href.starts_with('#') || href.starts_with("javascript:") {
+                        return Ok(());
+                    }
+                    let text = text.clone();
+                    el.on_end_tag(end_tag!(move |_| {
+                        let href = html_escape::decode_html_entities(&href);
+                        Ok(text.borrow_mut().write_text(&format!(" <{href}> "))?)
+                    }))
+                }),
+            ],
+            document_content_handlers: vec![doc_text!(|chunk| {
+                let mut text = text.borrow_mut();
+                match chunk.text_type() {
+                    // scripts and styles
+                    TextType::RawText | TextType::ScriptData => {}
+                    _ => text.text.push_str(chunk.as_str()),
+                }
+                if chunk.last_in_text_node() {
+                    let content = std::mem::take(&mut text.text);
+                    text.write_text(&html_escape::decode_html_entities(&content))?;
+                }
+                Ok(())
+            })],
+            adjust_charset_on_meta_tag: true,
+            // we only read the text, so ambiguous markup is not a problem
+            strict: false,
+            ..Settings::new()
+        },
+        |_: &[u8]| {},
+    );
+    let mut inp = SyncIoBridge::new(ai.inp);
+    let mut buf = vec![0; 64 * 1024];
+    loop {
+        let n = inp.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        rewriter.write(&buf[..n])?;
+    }
+    rewriter.end()?;
+    let mut text = text.borrow_mut();
+    text.newline()?;
+    text.out.flush()?;
+    Ok(())
+}
+
+#[async_trait]
+impl WritingFileAdapter for HtmlAdapter {
+    async fn adapt_write(
+        ai: AdaptInfo,
+        _detection_reason: &FileMatcher,
+        oup: Pin<Box<dyn AsyncWrite + Send>>,
+    ) -> Result<()> {
+        let oup_sync = SyncIoBridge::new(oup);
+        tokio::task::spawn_blocking(|| synchronous_html_to_text(ai, oup_sync))
+            .await?
+            .context("in synchronous html task")?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{preproc::loop_adapt, test_utils::*};
+    use pretty_assertions::assert_eq;
+
+    #[tokio::test]
+    async fn simple() -> Result<()> {
+        let filepath = test_data_dir().join("hello.html");
+        let (a, d) = simple_fs_adapt_info(&filepath).await?;
+        let o = adapted_to_vec(loop_adapt(&HtmlAdapter::new(), d, a).await?).await?;
+        assert_eq!(
+            String::from_utf8(o)?,
+            "PREFIX:Hello &amp; welcome
+PREFIX:Hello world!
+PREFIX:This is a test with a link <https://example.com/?a=1&b=2> in it.
+PREFIX:Tom & Jerry café top
+PREFIX:  keep
+PREFIX:    this
+PREFIX:one two
+PREFIX:
+"
+        );
+        Ok(())
+    }
+}
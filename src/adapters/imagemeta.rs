@@ -0,0 +1,472 @@
+use super::writing::WritingFileAdapter;
+use super::xml::{event_text, write_lines};
+use super::*;
+use anyhow::Result;
+use exif::{Context as ExifContext, Field, In, Tag, Value};
+use flate2::read::ZlibDecoder;
+use lazy_static::lazy_static;
+use quick_xml::Reader;
+use quick_xml::events::Event;
+use std::io::{BufWriter, Cursor, Read, Write};
+use tokio::io::AsyncWrite;
+use tokio_util::io::SyncIoBridge;
+
+static EXTENSIONS: &[&str] = &[
+    "jpg", "jpeg", "png", "tif", "tiff", "heic", "heif", "avif", "webp",
+];
+static MIME_TYPES: &[&str] = &[
+    "image/jpeg",
+    "image/png",
+    "image/tiff",
+    "image/heic",
+    "image/heif",
+    "image/avif",
+    "image/webp",
+];
+
+lazy_static! {
+    static ref METADATA: AdapterMeta = AdapterMeta {
+        name: "imagemeta".to_owned(),
+        version: 1,
+        description:
+            "Extracts EXIF, XMP and IPTC metadata and text chunks of images as key: value lines"
+                .to_owned(),
+        recurses: false,
+        fast_matchers: EXTENSIONS
+            .iter()
+            .map(|s| FastFileMatcher::FileExtension(s.to_string()))
+            .collect(),
+        slow_matchers: Some(
+            MIME_TYPES
+                .iter()
+                .map(|s| FileMatcher::MimeType(s.to_string()))
+                .collect()
+        ),
+        keep_fast_matchers_if_accurate: true,
+        disabled_by_default: false
+    };
+}
+
+#[derive(Default, Clone)]
+pub struct ImageMetaAdapter;
+
+impl ImageMetaAdapter {
+    pub fn new() -> Self {
+        Self
+    }
+}
+impl GetMetadata for ImageMetaAdapter {
+    fn metadata(&self) -> &AdapterMeta {
+        &METADATA
+    }
+}
+
+/// TIFF tags that contain XMP and IPTC, which are written separately
+const TAG_XMP: Tag = Tag(ExifContext::Tiff, 700);
+const TAG_IPTC: Tag = Tag(ExifContext::Tiff, 33723);
+
+fn field_bytes(field: &Field) -> Option<&[u8]> {
+    match &field.value {
+        Value::Byte(b) | Value::Undefined(b, _) => Some(b),
+        _ => None,
+    }
+}
+
+/// Converts degrees, minutes and seconds to decimal degrees
+fn gps_coordinate(exif: &exif::Exif, tag: Tag, ref_tag: Tag, negative: &str) -> Option<f64> {
+    let Value::Rational(dms) = &exif.get_field(tag, In::PRIMARY)?.value else {
+        return None;
+    };
+    let degrees = dms
+        .iter()
+        .zip([1.0, 60.0, 3600.0])
+        .map(|(v, div)| v.to_f64() / div)
+        .sum::<f64>();
+    let reference = exif.get_field(ref_tag, In::PRIMARY)?.display_value();
+    Some(if reference.to_string() == negative {
+        -degrees
+    } else {
+        degrees
+    })
+}
+
+fn write_exif(out: &mut impl Write, exif: &exif::Exif) -> Result<()> {
+    // thumbnail fields are a duplicate of the primary image
+    for field in exif.fields().filter(|f| f.ifd_num == In::PRIMARY) {
+        if field.tag == TAG_XMP {
+            write_xmp(out, field_bytes(field).unwrap_or_default())?;
+            continue;
+        }
+        if field.tag == TAG_IPTC {
+            write_iptc(out, field_bytes(field).unwrap_or_default())?;
+            continue;
+        }
+        if field.tag == Tag::MakerNote {
+            continue;
+        }
+        let value = match &field.value {
+            Value::Ascii(strings) => strings
+                .iter()
+                .map(|s| String::from_utf8_lossy(s).trim().to_owned())
+                .collect::<Vec<_>>()
+                .join(", "),
+            _ => field.display_value().with_unit(exif).to_string(),
+        };
+        write_lines(out, &format!("exif.{}: ", field.tag), &value)?;
+    }
+    let latitude = gps_coordinate(exif, Tag::GPSLatitude, Tag::GPSLatitudeRef, "S");
+    let longitude = gps_coordinate(exif, Tag::GPSLongitude, Tag::GPSLongitudeRef, "W");
+    if let (Some(latitude), Some(longitude)) = (latitude, longitude) {
+        writeln!(out, "exif.GPSPosition: {latitude:.6}, {longitude:.6}")?;
+    }
+    Ok(())
+}
+
+/// Writes the properties of an XMP packet as `xmp.prefix:Name: value`.
+///
+/// Values of lists (e.g. `dc:subject` keywords) are written as one line each.
+fn write_xmp(out: &mut impl Write, data: &[u8]) -> Result<()> {
+    let is_property = |name: &[u8]| !(name.starts_with(b"rdf:") || name.starts_with(b"x:"));
+    let mut reader = Reader::from_reader(data);
+    let mut properties: Vec<String> = Vec::new();
+    let mut text = String::new();
+    let mut buf = Vec::new();
+    loop {
+        let event = reader.read_event_into(&mut buf)?;
+        match &event {
+            Event::Start(e) | Event::Empty(e) => {
+                for a in e.attributes() {
+                    let a = a?;
+                    let key = a.key.as_ref();
+                    if is_property(key) && !key.starts_with(b"xmlns") && !key.starts_with(b"xml:") {
+                        let value = a.decode_and_unescape_value(e.decoder())?;
+                        let key = String::from_utf8_lossy(key);
+                        write_lines(out, &format!("xmp.{key}: "), &value)?;
+                    }
+                }
+                if matches!(event, Event::Start(_)) {
+                    let name = e.name();
+                    if is_property(name.as_ref()) {
+                        properties.push(String::from_utf8_lossy(name.as_ref()).into_owned());
+                    }
+                    text.clear();
+                }
+            }
+            Event::End(e) => {
+                if let Some(property) = properties.last() {
+                    write_lines(out, &format!("xmp.{property}: "), text.trim())?;
+                }
+                text.clear();
+                if is_property(e.name().as_ref()) {
+                    properties.pop();
+                }
+            }
+            Event::Eof => break,
+            event => {
+                if let Some(t) = event_text(event)? {
+                    text.push_str(&t);
+                }
+            }
+        }
+        buf.clear();
+    }
+    Ok(())
+}
+
+/// Writes the properties of an XMP packet, or as many of them as can be read if it is malformed
+fn write_xmp_lenient(out: &mut impl Write, data: &[u8]) -> Result<()> {
+    let mut properties = Vec::new();
+    // a broken packet shouldn't hide the other metadata of the image
+    if let Err(e) = write_xmp(&mut properties, data) {
+        warn!("could not read xmp packet: {e}");
+    }
+    out.write_all(&properties)?;
+    Ok(())
+}
+
+/// Returns the name of an IPTC IIM dataset of the application record
+fn iptc_name(dataset: u8) -> Option<&'static str> {
+    Some(match dataset {
+        5 => "ObjectName",
+        15 => "Category",
+        20 => "SupplementalCategories",
+        25 => "Keywords",
+        40 => "SpecialInstructions",
+        55 => "DateCreated",
+        60 => "TimeCreated",
+        80 => "By-line",
+        85 => "By-lineTitle",
+        90 => "City",
+        92 => "Sub-location",
+        95 => "Province-State",
+        100 => "Country-PrimaryLocationCode",
+        101 => "Country-PrimaryLocationName",
+        103 => "OriginalTransmissionReference",
+        105 => "Headline",
+        110 => "Credit",
+        115 => "Source",
+        116 => "CopyrightNotice",
+        118 => "Contact",
+        120 => "Caption-Abstract",
+        122 => "Writer-Editor",
+        _ => return None,
+    })
+}
+
+/// Writes the application record (2:xx) datasets of IPTC IIM data as `iptc.Name: value`
+fn write_iptc(out: &mut impl Write, mut data: &[u8]) -> Result<()> {
+    while let [0x1c, record, dataset, hi, lo, rest @ ..] = data {
+        // extended datasets (with the high bit set) are not used for text
+        let len = usize::from(u16::from_be_bytes([*hi, *lo]) & 0x7fff);
+        let Some(value) = rest.get(..len) else { break };
+        if *record == 2
+            && let Some(name) = iptc_name(*dataset)
+        {
+            write_lines(
+                out,
+                &format!("iptc.{name}: "),
+                &String::from_utf8_lossy(value),
+            )?;
+        }
+        data = &rest[len..];
+    }
+    Ok(())
+}
+
+/// Writes the IPTC data of the image resource blocks of a photoshop APP13 segment
+fn write_photoshop_resources(out: &mut impl Write, mut data: &[u8]) -> Result<()> {
+    while let [b'8', b'B', b'I', b'M', id_hi, id_lo, name_len, rest @ ..] = data {
+        // the pascal string name including the length byte is padded to an even length
+        let name_size = (usize::from(*name_len) + 1).next_multiple_of(2) - 1;
+        let Some([s0, s1, s2, s3, rest @ ..]) = rest.get(name_size..) else {
+            break;
+        };
+        let size = u32::from_be_bytes([*s0, *s1, *s2, *s3]) as usize;
+        let Some(resource) = rest.get(..size) else {
+            break;
+        };
+        if [*id_hi, *id_lo] == [0x04, 0x04] {
+            write_iptc(out, resource)?;
+        }
+        data = rest.get(size.next_multiple_of(2)..).unwrap_or_default();
+    }
+    Ok(())
+}
+
+const XMP_JPEG_HEADER: &[u8] = b"http://ns.adobe.com/xap/1.0/\0";
+
+fn write_jpeg(out: &mut impl Write, data: &[u8]) -> Result<()> {
+    let mut pos = 2;
+    while let Some([0xff, marker, hi, lo]) = data.get(pos..pos + 4) {
+        // start of scan, the metadata segments come before the image data
+        if *marker == 0xda {
+            break;
+        }
+        let len = usize::from(u16::from_be_bytes([*hi, *lo]));
+        let Some(segment) = data.get(pos + 4..pos + 2 + len) else {
+            break;
+        };
+        match marker {
+            0xe1 => {
+                if let Some(xmp) = segment.strip_prefix(XMP_JPEG_HEADER) {
+                    write_xmp_lenient(out, xmp)?;
+                }
+            }
+            0xed => {
+                if let Some(resources) = segment.strip_prefix(b"Photoshop 3.0\0") {
+                    write_photoshop_resources(out, resources)?;
+                }
+            }
+            0xfe => write_lines(out, "comment: ", &String::from_utf8_lossy(segment))?,
+            _ => {}
+        }
+        pos += 2 + len;
+    }
+    Ok(())
+}
+
+/// Splits a null terminated string off the start of `data`
+fn split_null(data: &[u8]) -> Option<(&[u8], &[u8])> {
+    let end = data.iter().position(|&b| b == 0)?;
+    Some((&data[..end], &data[end + 1..]))
+}
+
+/// Inflates the text of a compressed png text chunk, skipping the chunk if it is corrupt
+fn inflate(keyword: &[u8], data: &[u8]) -> Option<Vec<u8>> {
+    let mut inflated = Vec::new();
+    match ZlibDecoder::new(data).read_to_end(&mut inflated) {
+        Ok(_) => Some(inflated),
+        Err(e) => {
+            warn!(
+                "could not inflate png text chunk {}: {e}",
+                String::from_utf8_lossy(keyword)
+            );
+            None
+        }
+    }
+}
+
+/// Writes the text chunks of a png file as `png.keyword: text`
+fn write_png(out: &mut impl Write, data: &[u8]) -> Result<()> {
+    let mut pos = 8;
+    while let Some([l0, l1, l2, l3, t0, t1, t2, t3]) = data.get(pos..pos + 8) {
+        let len = u32::from_be_bytes([*l0, *l1, *l2, *l3]) as usize;
+        let Some(chunk) = data.get(pos + 8..pos + 8 + len) else {
+            break;
+        };
+        let text = match &[*t0, *t1, *t2, *t3] {
+            b"tEXt" => split_null(chunk).map(|(k, v)| (k, v.to_vec())),
+            b"zTXt" => match split_null(chunk) {
+                Some((k, [0, compressed @ ..])) => inflate(k, compressed).map(|v| (k, v)),
+                _ => None,
+            },
+            b"iTXt" => match split_null(chunk) {
+                // keyword, compression flag, method, language tag and translated keyword
+                Some((k, [compressed, _, rest @ ..])) => {
+                    match split_null(rest).and_then(|(_, rest)| split_null(rest)) {
+                        Some((_, v)) if *compressed == 1 => inflate(k, v).map(|v| (k, v)),
+                        Some((_, v)) => Some((k, v.to_vec())),
+                        None => None,
+                    }
+                }
+                _ => None,
+            },
+            b"IEND" => break,
+            _ => None,
+        };
+        match text {
+            Some((b"XML:com.adobe.xmp", xmp)) => write_xmp_lenient(out, &xmp)?,
+            Some((keyword, text)) => write_lines(
+                out,
+                &format!("png.{}: ", String::from_utf8_lossy(keyword)),
+                &String::from_utf8_lossy(&text),
+            )?,
+            None => {}
+        }
+        // length, type and crc
+        pos += len + 12;
+    }
+    Ok(())
+}
+
+fn write_webp(out: &mut impl Write, data: &[u8]) -> Result<()> {
+    let mut pos = 12;
+    while let Some([t0, t1, t2, t3, l0, l1, l2, l3]) = data.get(pos..pos + 8) {
+        let len = u32::from_le_bytes([*l0, *l1, *l2, *l3]) as usize;
+        let Some(chunk) = data.get(pos + 8..pos + 8 + len) else {
+            break;
+        };
+        if &[*t0, *t1, *t2, *t3] == b"XMP " {
+            write_xmp_lenient(out, chunk)?;
+        }
+        pos += 8 + len.next_multiple_of(2);
+    }
+    Ok(())
+}
+
+fn synchronous_dump_imagemeta(ai: AdaptInfo, out: impl Write) -> Result<()> {
+    let mut data = Vec::new();
+    SyncIoBridge::new(ai.inp).read_to_end(&mut data)?;
+    let mut out = BufWriter::new(out);
+    match exif::Reader::new().read_from_container(&mut Cursor::new(&data)) {
+        Ok(exif) => write_exif(&mut out, &exif)?,
+        Err(exif::Error::NotFound(_)) => {}
+        // the other metadata may still be readable
+        Err(e) => warn!("{}: could not read exif: {e}", ai.filepath_hint.display()),
+    }
+    if data.starts_with(b"\xff\xd8") {
+        write_jpeg(&mut out, &data)?;
+    } else if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+        write_png(&mut out, &data)?;
+    } else if data.starts_with(b"RIFF") && data.get(8..12) == Some(b"WEBP") {
+        write_webp(&mut out, &data)?;
+    }
+    out.flush()?;
+    Ok(())
+}
+
+#[async_trait]
+impl WritingFileAdapter for ImageMetaAdapter {
+    async fn adapt_write(
+        ai: AdaptInfo,
+        _detection_reason: &FileMatcher,
+        oup: Pin<Box<dyn AsyncWrite + Send>>,
+    ) -> Result<()> {
+        let oup_sync = SyncIoBridge::new(oup);
+        tokio::task::spawn_blocking(|| synchronous_dump_imagemeta(ai, oup_sync))
+            .await?
+            .context("in synchronous imagemeta task")?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use pretty_assertions::assert_eq;
+
+    #[tokio::test]
+    async fn jpeg() -> Result<()> {
+        assert_eq!(
+            adapt_test_file(&ImageMetaAdapter::new(), "hello.jpg", true).await?,
+            "PREFIX:exif.Make: Canon
+PREFIX:exif.Model: Canon EOS 5D
+PREFIX:exif.GPSLatitudeRef: N
+PREFIX:exif.GPSLatitude: 52 deg 31 min 12 sec N
+PREFIX:exif.GPSLongitudeRef: E
+PREFIX:exif.GPSLongitude: 13 deg 24 min 36 sec E
+PREFIX:exif.GPSPosition: 52.520000, 13.410000
+PREFIX:xmp.xmp:Rating: 5
+PREFIX:xmp.dc:title: Sunset & sea
+PREFIX:xmp.dc:subject: beach
+PREFIX:xmp.dc:subject: holiday
+PREFIX:iptc.Caption-Abstract: A caption
+PREFIX:iptc.Keywords: beach
+PREFIX:iptc.Keywords: holiday
+PREFIX:iptc.City: Berlin
+PREFIX:comment: a comment
+PREFIX:
+"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn malformed_chunks() -> Result<()> {
+        let chunk = |kind: &[u8], data: &[u8]| {
+            let mut chunk = (data.len() as u32).to_be_bytes().to_vec();
+            chunk.extend_from_slice(kind);
+            chunk.extend_from_slice(data);
+            // the crc isn't checked
+            chunk.extend_from_slice(&[0; 4]);
+            chunk
+        };
+        let xmp = b"<x:xmpmeta><dc:title>Sea</dc:title></x:x";
+        assert!(write_xmp(&mut Vec::new(), xmp).is_err());
+        let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+        png.extend(chunk(
+            b"iTXt",
+            &[b"XML:com.adobe.xmp\0\0\0\0\0".as_slice(), xmp].concat(),
+        ));
+        png.extend(chunk(b"zTXt", b"Comment\0\0not zlib data"));
+        png.extend(chunk(b"tEXt", b"Author\0Jane"));
+        let mut out = Vec::new();
+        write_png(&mut out, &png)?;
+        assert_eq!(
+            String::from_utf8(out)?,
+            "xmp.dc:title: Sea\npng.Author: Jane\n"
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn png_text() -> Result<()> {
+        let text = adapt_test_file(&ImageMetaAdapter::new(), "../exif.png", true).await?;
+        assert!(
+            text.starts_with("PREFIX:png.parameters: (illustration:1.0), 1girl, solo,"),
+            "{text}"
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,396 @@
+use super::blocking::{EntrySender, SpooledFile, blocking_entries};
+use super::*;
+use crate::print_bytes;
+use anyhow::{Result, bail};
+use lazy_static::lazy_static;
+use std::collections::HashSet;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+static EXTENSIONS: &[&str] = &["iso"];
+
+lazy_static! {
+    static ref METADATA: AdapterMeta = AdapterMeta {
+        name: "iso".to_owned(),
+        version: 1,
+        description: "Reads an ISO 9660 disk image (with Joliet / Rock Ridge file names) and recurses down into its contents.\nUDF is not supported, so images without an ISO 9660 file system (e.g. some DVD and Blu-ray images) can't be read".to_owned(),
+        recurses: true,
+        fast_matchers: EXTENSIONS
+            .iter()
+            .map(|s| FastFileMatcher::FileExtension(s.to_string()))
+            .collect(),
+        slow_matchers: Some(vec![FileMatcher::MimeType("application/x-cd-image".to_owned())]),
+        keep_fast_matchers_if_accurate: true,
+        disabled_by_default: false
+    };
+}
+#[derive(Default, Clone)]
+pub struct IsoAdapter;
+
+impl IsoAdapter {
+    pub fn new() -> Self {
+        Self
+    }
+}
+impl GetMetadata for IsoAdapter {
+    fn metadata(&self) -> &AdapterMeta {
+        &METADATA
+    }
+}
+
+const SECTOR_SIZE: u64 = 2048;
+
+/// A directory record, see ECMA-119 9.1
+struct Record {
+    extent: u32,
+    size: u32,
+    flags: u8,
+    name: Vec<u8>,
+    system_use: Vec<u8>,
+}
+
+impl Record {
+    const DIRECTORY: u8 = 0x02;
+    const MULTI_EXTENT: u8 = 0x80;
+
+    fn parse(buf: &[u8]) -> Result<Self> {
+        let len = buf[0] as usize;
+        if len < 34 || len > buf.len() || 33 + buf[32] as usize > len {
+            bail!("invalid directory record");
+        }
+        let name_len = buf[32] as usize;
+        // the name is padded to an even length
+        let system_use_start = (33 + name_len + (name_len + 1) % 2).min(len);
+        Ok(Self {
+            extent: u32::from_le_bytes(buf[2..6].try_into()?),
+            size: u32::from_le_bytes(buf[10..14].try_into()?),
+            flags: buf[25],
+            name: buf[33..33 + name_len].to_vec(),
+            system_use: buf[system_use_start..len].to_vec(),
+        })
+    }
+
+    fn is_dot_or_dotdot(&self) -> bool {
+        self.name == [0] || self.name == [1]
+    }
+}
+
+/// The parts of the Rock Ridge extensions (RRIP) of a directory record that matter for reading files
+#[derive(Default)]
+struct RockRidge {
+    name: Option<String>,
+    is_symlink: bool,
+    /// the location of a directory that was moved elsewhere because of the depth limit of ISO 9660
+    child_link: Option<u32>,
+    /// marks the moved directory in its new place
+    is_relocated: bool,
+}
+
+/// Reads a range of the image, seeking before each read so several sections can be chained
+struct FileSection<'a> {
+    file: &'a std::fs::File,
+    offset: u64,
+    remaining: u64,
+}
+
+impl Read for FileSection<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.remaining == 0 {
+            return Ok(0);
+        }
+        let mut file = self.file;
+        file.seek(SeekFrom::Start(self.offset))?;
+        let len = buf
+            .len()
+            .min(self.remaining.try_into().unwrap_or(usize::MAX));
+        let n = file.read(&mut buf[..len])?;
+        self.offset += n as u64;
+        self.remaining -= n as u64;
+        Ok(n)
+    }
+}
+
+struct Image<'a> {
+    file: &'a std::fs::File,
+    joliet: bool,
+    /// bytes to skip at the start of each system use area if Rock Ridge is used
+    susp_skip: Option<usize>,
+    visited: HashSet<u32>,
+}
+
+impl Image<'_> {
+    fn read_at(&self, extent: u32, offset: u64, len: u64) -> Result<Vec<u8>> {
+        let mut file = self.file;
+        file.seek(SeekFrom::Start(extent as u64 * SECTOR_SIZE + offset))?;
+        let mut buf = Vec::new();
+        file.take(len).read_to_end(&mut buf)?;
+        if (buf.len() as u64) < len {
+            bail!("iso image is truncated");
+        }
+        Ok(buf)
+    }
+
+    fn read_directory(&self, extent: u32, size: Option<u32>) -> Result<Vec<Record>> {
+        let size = match size {
+            Some(size) => size,
+            // relocated directories are only referenced by location, their size is in their "." entry
+            None => Record::parse(&self.read_at(extent, 0, 256)?)?.size,
+        };
+        let buf = self.read_at(extent, 0, size as u64)?;
+        let mut records = Vec::new();
+        let mut pos = 0;
+        while pos < buf.len() {
+            if buf[pos] == 0 {
+                // records don't cross sector boundaries, the rest of the sector is zero padding
+                pos = (pos / SECTOR_SIZE as usize + 1) * SECTOR_SIZE as usize;
+                continue;
+            }
+            let record = Record::parse(&buf[pos..])?;
+            pos += buf[pos] as usize;
+            records.push(record);
+        }
+        Ok(records)
+    }
+
+    fn rock_ridge(&self, record: &Record) -> Result<RockRidge> {
+        let mut rr = RockRidge::default();
+        let Some(skip) = self.susp_skip else {
+            return Ok(rr);
+        };
+        let mut area = record.system_use.get(skip..).unwrap_or_default().to_vec();
+        let mut name = Vec::new();
+        let mut continuations = 0;
+        loop {
+            let mut continuation = None;
+            let mut pos = 0;
+            while pos + 4 <= area.len() {
+                let len = area[pos + 2] as usize;
+                if len < 4 || pos + len > area.len() {
+                    break;
+                }
+                let entry = &area[pos..pos + len];
+                match &entry[0..2] {
+                    b"NM" if len >= 5 => {
+                        name.extend_from_slice(&entry[5..]);
+                        if entry[4] & 0x01 == 0 {
+                            rr.name = Some(String::from_utf8_lossy(&name).into_owned());
+                        }
+                    }
+                    b"SL" => rr.is_symlink = true,
+                    b"CL" if len >= 8 => {
+                        rr.child_link = Some(u32::from_le_bytes(entry[4..8].try_into()?))
+                    }
+                    b"RE" => rr.is_relocated = true,
+                    b"CE" if len >= 28 => {
+                        continuation = Some((
+                            u32::from_le_bytes(entry[4..8].try_into()?),
+                            u32::from_le_bytes(entry[12..16].try_into()?),
+                            u32::from_le_bytes(entry[20..24].try_into()?),
+                        ))
+                    }
+                    b"ST" => break,
+                    _ => {}
+                }
+                pos += len;
+            }
+            match continuation {
+                Some((extent, offset, len)) if continuations < 32 => {
+                    area = self.read_at(extent, offset as u64, len as u64)?;
+                    continuations += 1;
+                }
+                _ => return Ok(rr),
+            }
+        }
+    }
+
+    fn file_name(&self, record: &Record, rr: &RockRidge) -> String {
+        if let Some(name) = &rr.name {
+            return name.clone();
+        }
+        let name = if self.joliet {
+            let utf16: Vec<u16> = record
+                .name
+                .chunks_exact(2)
+                .map(|c| u16::from_be_bytes([c[0], c[1]]))
+                .collect();
+            String::from_utf16_lossy(&utf16)
+        } else {
+            String::from_utf8_lossy(&record.name).into_owned()
+        };
+        // strip the version number (";1") and the dot of names without an extension
+        let name = name.split(';').next().unwrap_or_default();
+        name.strip_suffix('.').unwrap_or(name).to_owned()
+    }
+
+    fn send_directory(
+        &mut self,
+        ai: &AdaptInfo,
+        extent: u32,
+        size: Option<u32>,
+        dir_path: &Path,
+        entries: &EntrySender,
+    ) -> Result<()> {
+        if !self.visited.insert(extent) {
+            bail!("directory loop in iso image at sector {extent}");
+        }
+        // a file larger than 4 GiB is split into multiple records with the same name
+        let mut extents = Vec::new();
+        for record in self.read_directory(extent, size)? {
+            if record.is_dot_or_dotdot() {
+                continue;
+            }
+            let rr = self.rock_ridge(&record)?;
+            if rr.is_symlink || rr.is_relocated {
+                continue;
+            }
+            let path = dir_path.join(self.file_name(&record, &rr));
+            if let Some(child) = rr.child_link {
+                self.send_directory(ai, child, None, &path, entries)?;
+            } else if record.flags & Record::DIRECTORY != 0 {
+                self.send_directory(ai, record.extent, Some(record.size), &path, entries)?;
+            } else {
+                extents.push((record.extent, record.size));
+                if record.flags & Record::MULTI_EXTENT != 0 {
+                    continue;
+                }
+                let size: u64 = extents.iter().map(|(_, size)| *size as u64).sum();
+                debug!(
+                    "{}{}|{}: {}",
+                    ai.line_prefix,
+                    ai.filepath_hint.display(),
+                    path.display(),
+                    print_bytes(size as f64)
+                );
+                let mut contents: Box<dyn Read> = Box::new(std::io::empty());
+                for (extent, size) in extents.drain(..) {
+                    let part = FileSection {
+                        file: self.file,
+                        offset: extent as u64 * SECTOR_SIZE,
+                        remaining: size as u64,
+                    };
+                    contents = Box::new(contents.chain(part));
+                }
+                entries.send(path, contents)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn read_entries(ai: AdaptInfo, entries: &EntrySender) -> Result<()> {
+    // the volume descriptors are at the start, but the directories and files can be anywhere
+    let spooled = SpooledFile::new(&ai.filepath_hint, ai.is_real_file, ai.inp)?;
+    let file = spooled.open()?;
+    let ai = AdaptInfo {
+        inp: Box::pin(tokio::io::empty()),
+        ..ai
+    };
+    let mut image = Image {
+        file: &file,
+        joliet: false,
+        susp_skip: None,
+        visited: HashSet::new(),
+    };
+    let mut primary_root = None;
+    let mut joliet_root = None;
+    for sector in 16.. {
+        let descriptor = image
+            .read_at(sector, 0, SECTOR_SIZE)
+            .with_context(|| format!("reading iso image {}", ai.filepath_hint.display()))?;
+        if &descriptor[1..6] != b"CD001" {
+            bail!("{} is not an iso 9660 image", ai.filepath_hint.display());
+        }
+        let root = || Record::parse(&descriptor[156..190]);
+        match descriptor[0] {
+            1 => primary_root = Some(root()?),
+            // a supplementary volume descriptor with one of the UCS-2 escape sequences
+            2 if matches!(&descriptor[88..91], b"%/@" | b"%/C" | b"%/E") => {
+                joliet_root = Some(root()?)
+            }
+            255 => break,
+            _ => {}
+        }
+    }
+    let primary_root = primary_root.context("iso image has no primary volume descriptor")?;
+    // Rock Ridge is announced by a SUSP "SP" entry in the "." record of the root directory
+    let root_dot = image
+        .read_directory(primary_root.extent, Some(primary_root.size))?
+        .into_iter()
+        .next();
+    if let Some(dot) = root_dot {
+        let su = &dot.system_use;
+        if su.len() >= 7 && su.starts_with(b"SP") && su[4..6] == [0xbe, 0xef] {
+            image.susp_skip = Some(su[6] as usize);
+        }
+    }
+    // prefer Rock Ridge names since they are not truncated, then Joliet
+    let root = match joliet_root {
+        Some(root) if image.susp_skip.is_none() => {
+            image.joliet = true;
+            root
+        }
+        _ => primary_root,
+    };
+    image.send_directory(&ai, root.extent, Some(root.size), Path::new(""), entries)
+}
+
+#[async_trait]
+impl FileAdapter for IsoAdapter {
+    async fn adapt(
+        &self,
+        ai: AdaptInfo,
+        _detection_reason: &FileMatcher,
+    ) -> Result<AdaptedFilesIterBox> {
+        Ok(blocking_entries(ai, read_entries))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{preproc::loop_adapt, test_utils::*};
+    use async_compression::tokio::bufread::GzipDecoder;
+    use pretty_assertions::assert_eq;
+    use tokio::{fs::File, io::BufReader};
+
+    async fn adapt_image(name: &str) -> Result<String> {
+        // the images are stored compressed since they are mostly zeroes
+        let filepath = test_data_dir().join(format!("{name}.gz"));
+        let inp = GzipDecoder::new(BufReader::new(File::open(&filepath).await?));
+        let (a, d) = simple_adapt_info(&PathBuf::from(name), Box::pin(inp));
+        let o = adapted_to_vec(loop_adapt(&IsoAdapter::new(), d, a).await?).await?;
+        Ok(String::from_utf8(o)?)
+    }
+
+    #[tokio::test]
+    async fn joliet() -> Result<()> {
+        assert_eq!(
+            adapt_image("joliet.iso").await?,
+            "PREFIX:Read Me First, Please.txt: Please read me first,
+PREFIX:Read Me First, Please.txt: before the other readme.
+PREFIX:Read Me First, Please.txt: 
+PREFIX:readme.txt: Joliet keeps long names like \"Read Me First, Please.txt\".
+PREFIX:readme.txt: 
+"
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn rockridge() -> Result<()> {
+        // includes a deeply nested directory that was relocated to rr_moved
+        assert_eq!(
+            adapt_image("rockridge.iso").await?,
+            "PREFIX:1/2/3/4/5/6/7/8/9/10/relocation.txt: My sister opened a computer store in Hawaii.
+PREFIX:1/2/3/4/5/6/7/8/9/10/relocation.txt: She sells C shells by the seashore.
+PREFIX:1/2/3/4/5/6/7/8/9/10/relocation.txt: 
+PREFIX:readme.txt: Live fast, die young, and leave a flat patch of fur on the highway!
+PREFIX:readme.txt:                 -- The Squirrels' Motto (The \"Hell's Angels of Nature\")
+PREFIX:readme.txt: 
+PREFIX:Read Me First, Please.txt: 
+PREFIX:Really really really really really really really Really really really really really really reallyReally really really really really really really long.txt: 
+"
+        );
+        Ok(())
+    }
+}
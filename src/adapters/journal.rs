@@ -0,0 +1,370 @@
+use super::blocking::SpooledFile;
+use super::writing::WritingFileAdapter;
+use super::xml::write_lines;
+use super::*;
+use anyhow::{Result, bail};
+use chrono::{DateTime, SecondsFormat};
+use lazy_static::lazy_static;
+use log::*;
+use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use tokio::io::AsyncWrite;
+use tokio_util::io::SyncIoBridge;
+
+// journal files that were not closed cleanly are renamed to .journal~
+static EXTENSIONS: &[&str] = &["journal", "journal~"];
+static MIME_TYPES: &[&str] = &["application/x-journal"];
+
+lazy_static! {
+    static ref METADATA: AdapterMeta = AdapterMeta {
+        name: "journal".to_owned(),
+        version: 1,
+        description: "Reads systemd journal files directly (without journalctl) and outputs one line per entry with its timestamp, unit and message, e.g. `2024-03-01T10:15:30.123456Z nginx.service: Started server`.\nAdditional fields can be added with --rga-journal-fields".to_owned(),
+        recurses: false,
+        fast_matchers: EXTENSIONS
+            .iter()
+            .map(|s| FastFileMatcher::FileExtension(s.to_string()))
+            .collect(),
+        slow_matchers: Some(
+            MIME_TYPES
+                .iter()
+                .map(|s| FileMatcher::MimeType(s.to_string()))
+                .collect()
+        ),
+        keep_fast_matchers_if_accurate: true,
+        disabled_by_default: false
+    };
+}
+
+#[derive(Default, Clone)]
+pub struct JournalAdapter;
+
+impl JournalAdapter {
+    pub fn new() -> Self {
+        Self
+    }
+}
+impl GetMetadata for JournalAdapter {
+    fn metadata(&self) -> &AdapterMeta {
+        &METADATA
+    }
+}
+
+// see https://systemd.io/JOURNAL_FILE_FORMAT/
+const SIGNATURE: &[u8] = b"LPKSHHRH";
+const HEADER_INCOMPATIBLE_COMPRESSED_XZ: u32 = 1 << 0;
+const HEADER_INCOMPATIBLE_COMPRESSED_LZ4: u32 = 1 << 1;
+const HEADER_INCOMPATIBLE_KEYED_HASH: u32 = 1 << 2;
+const HEADER_INCOMPATIBLE_COMPRESSED_ZSTD: u32 = 1 << 3;
+const HEADER_INCOMPATIBLE_COMPACT: u32 = 1 << 4;
+const HEADER_INCOMPATIBLE_SUPPORTED: u32 = HEADER_INCOMPATIBLE_COMPRESSED_XZ
+    | HEADER_INCOMPATIBLE_COMPRESSED_LZ4
+    | HEADER_INCOMPATIBLE_KEYED_HASH
+    | HEADER_INCOMPATIBLE_COMPRESSED_ZSTD
+    | HEADER_INCOMPATIBLE_COMPACT;
+
+const OBJECT_DATA: u8 = 1;
+const OBJECT_ENTRY: u8 = 3;
+const OBJECT_ENTRY_ARRAY: u8 = 6;
+const OBJECT_COMPRESSED_XZ: u8 = 1 << 0;
+const OBJECT_COMPRESSED_LZ4: u8 = 1 << 1;
+const OBJECT_COMPRESSED_ZSTD: u8 = 1 << 2;
+const OBJECT_HEADER_SIZE: u64 = 16;
+/// journald drops fields larger than this, so a bigger object size means the file is corrupt
+const MAX_OBJECT_SIZE: u64 = 768 * 1024 * 1024;
+
+/// Fields that name the unit of an entry, in order of preference
+static UNIT_FIELDS: &[&str] = &[
+    "_SYSTEMD_UNIT",
+    "_SYSTEMD_USER_UNIT",
+    "SYSLOG_IDENTIFIER",
+    "_COMM",
+];
+
+fn read_u32(buf: &[u8], offset: usize) -> Result<u32> {
+    let bytes = buf
+        .get(offset..offset + 4)
+        .context("journal object too short")?;
+    Ok(u32::from_le_bytes(bytes.try_into()?))
+}
+
+fn read_u64(buf: &[u8], offset: usize) -> Result<u64> {
+    let bytes = buf
+        .get(offset..offset + 8)
+        .context("journal object too short")?;
+    Ok(u64::from_le_bytes(bytes.try_into()?))
+}
+
+struct JournalFile<R> {
+    inp: R,
+    /// Compact journals store offsets as u32 and have a larger data object header
+    compact: bool,
+    n_entries: u64,
+    entry_array_offset: u64,
+}
+
+impl<R: Read + Seek> JournalFile<R> {
+    fn open(mut inp: R) -> Result<Self> {
+        let mut header = [0u8; 184];
+        inp.read_exact(&mut header)
+            .context("journal file header too short")?;
+        if !header.starts_with(SIGNATURE) {
+            bail!("not a journal file");
+        }
+        let incompatible_flags = read_u32(&header, 12)?;
+        if incompatible_flags & !HEADER_INCOMPATIBLE_SUPPORTED != 0 {
+            bail!("unsupported journal features {incompatible_flags:#x}");
+        }
+        Ok(Self {
+            inp,
+            compact: incompatible_flags & HEADER_INCOMPATIBLE_COMPACT != 0,
+            n_entries: read_u64(&header, 152)?,
+            entry_array_offset: read_u64(&header, 176)?,
+        })
+    }
+
+    /// Reads the object at the given offset, returning its flags and the bytes after the object header
+    fn read_object(&mut self, offset: u64, object_type: u8) -> Result<(u8, Vec<u8>)> {
+        self.inp.seek(SeekFrom::Start(offset))?;
+        let mut header = [0u8; OBJECT_HEADER_SIZE as usize];
+        self.inp.read_exact(&mut header)?;
+        if header[0] != object_type {
+            bail!(
+                "expected object of type {object_type} at {offset:#x}, found {}",
+                header[0]
+            );
+        }
+        let size = read_u64(&header, 8)?;
+        if !(OBJECT_HEADER_SIZE..=MAX_OBJECT_SIZE).contains(&size) {
+            bail!("invalid object size {size} at {offset:#x}");
+        }
+        let mut body = vec![0u8; (size - OBJECT_HEADER_SIZE) as usize];
+        self.inp.read_exact(&mut body)?;
+        Ok((header[1], body))
+    }
+
+    /// Reads a list of offsets to other objects, which are u32 in compact journals
+    fn read_offsets(&self, items: &[u8], stride: usize) -> Vec<u64> {
+        items
+            .chunks_exact(stride)
+            .map(|item| {
+                if self.compact {
+                    u32::from_le_bytes(item[..4].try_into().unwrap()) as u64
+                } else {
+                    u64::from_le_bytes(item[..8].try_into().unwrap())
+                }
+            })
+            .collect()
+    }
+
+    /// The offsets of all entries in the file, in order
+    fn entry_offsets(&mut self) -> Result<Vec<u64>> {
+        let mut offsets = Vec::new();
+        let mut array_offset = self.entry_array_offset;
+        while array_offset != 0 && (offsets.len() as u64) < self.n_entries {
+            let (_, body) = self.read_object(array_offset, OBJECT_ENTRY_ARRAY)?;
+            let next = read_u64(&body, 0)?;
+            let stride = if self.compact { 4 } else { 8 };
+            // unused slots at the end of the last array are zero
+            offsets.extend(
+                self.read_offsets(&body[8..], stride)
+                    .into_iter()
+                    .filter(|&o| o != 0),
+            );
+            // arrays are appended to the file, so a chain that goes backwards is corrupt
+            if next <= array_offset {
+                break;
+            }
+            array_offset = next;
+        }
+        offsets.truncate(self.n_entries as usize);
+        Ok(offsets)
+    }
+
+    /// Reads the `FIELD=value` payload of a data object
+    fn read_data(&mut self, offset: u64) -> Result<Vec<u8>> {
+        let (flags, body) = self.read_object(offset, OBJECT_DATA)?;
+        let payload_start = if self.compact { 56 } else { 48 };
+        let payload = body.get(payload_start..).context("data object too short")?;
+        Ok(if flags & OBJECT_COMPRESSED_XZ != 0 {
+            let mut data = Vec::new();
+            xz2::read::XzDecoder::new(payload).read_to_end(&mut data)?;
+            data
+        } else if flags & OBJECT_COMPRESSED_LZ4 != 0 {
+            // prefixed with the uncompressed size
+            let size = read_u64(payload, 0)?;
+            if size > MAX_OBJECT_SIZE {
+                bail!("invalid uncompressed size {size} at {offset:#x}");
+            }
+            lz4_flex::block::decompress(&payload[8..], size as usize)?
+        } else if flags & OBJECT_COMPRESSED_ZSTD != 0 {
+            zstd::stream::decode_all(payload)?
+        } else {
+            payload.to_vec()
+        })
+    }
+
+    /// Reads the realtime timestamp (µs since the epoch) and the fields of an entry
+    fn read_entry(&mut self, offset: u64) -> Result<(u64, Vec<(String, String)>)> {
+        let (_, body) = self.read_object(offset, OBJECT_ENTRY)?;
+        let realtime = read_u64(&body, 8)?;
+        // regular items also contain the hash of the data object
+        let stride = if self.compact { 4 } else { 16 };
+        let items = body.get(48..).context("entry object too short")?;
+        let mut fields = Vec::new();
+        for data_offset in self.read_offsets(items, stride) {
+            let data = self.read_data(data_offset)?;
+            let data = String::from_utf8_lossy(&data);
+            if let Some((name, value)) = data.split_once('=') {
+                fields.push((name.to_owned(), value.to_owned()));
+            }
+        }
+        Ok((realtime, fields))
+    }
+}
+
+fn format_timestamp(realtime: u64) -> String {
+    i64::try_from(realtime)
+        .ok()
+        .and_then(DateTime::from_timestamp_micros)
+        .map(|t| t.to_rfc3339_opts(SecondsFormat::Micros, true))
+        .unwrap_or_else(|| realtime.to_string())
+}
+
+fn write_entry(
+    out: &mut impl Write,
+    realtime: u64,
+    fields: &[(String, String)],
+    selected_fields: &[String],
+) -> Result<()> {
+    let field = |name: &str| {
+        fields
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.as_str())
+    };
+    let mut prefix = format_timestamp(realtime);
+    if let Some(unit) = UNIT_FIELDS.iter().find_map(|name| field(name)) {
+        prefix.push(' ');
+        prefix.push_str(unit);
+    }
+    for name in selected_fields {
+        for (_, value) in fields.iter().filter(|(n, _)| n == name) {
+            let value = value.split_whitespace().collect::<Vec<_>>().join(" ");
+            prefix.push_str(&format!(" {name}={value}"));
+        }
+    }
+    match field("MESSAGE").filter(|m| !m.trim().is_empty()) {
+        Some(message) => write_lines(out, &format!("{prefix}: "), message)?,
+        None => writeln!(out, "{prefix}")?,
+    }
+    Ok(())
+}
+
+fn synchronous_dump_journal(ai: AdaptInfo, out: impl Write) -> Result<()> {
+    let AdaptInfo {
+        is_real_file,
+        filepath_hint,
+        config,
+        inp,
+        ..
+    } = ai;
+    // the entry arrays form a linked list that starts at an offset in the header
+    let spooled = SpooledFile::new(&filepath_hint, is_real_file, inp)?;
+    let mut journal = JournalFile::open(BufReader::new(spooled.open()?))?;
+    let mut out = BufWriter::new(out);
+    for offset in journal.entry_offsets()? {
+        match journal.read_entry(offset) {
+            Ok((realtime, fields)) => write_entry(
+                &mut out,
+                realtime,
+                &fields,
+                &config.adapter_options.journal_fields,
+            )?,
+            // journals of crashed machines are often partially written, so keep going
+            Err(e) => warn!(
+                "{}: skipping journal entry at {offset:#x}: {e}",
+                filepath_hint.display()
+            ),
+        }
+    }
+    out.flush()?;
+    Ok(())
+}
+
+#[async_trait]
+impl WritingFileAdapter for JournalAdapter {
+    async fn adapt_write(
+        ai: AdaptInfo,
+        _detection_reason: &FileMatcher,
+        oup: Pin<Box<dyn AsyncWrite + Send>>,
+    ) -> Result<()> {
+        let oup_sync = SyncIoBridge::new(oup);
+        tokio::task::spawn_blocking(|| synchronous_dump_journal(ai, oup_sync))
+            .await?
+            .context("in synchronous journal task")?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{config::AdapterOptions, test_utils::*};
+    use pretty_assertions::assert_eq;
+
+    async fn adapt_file(name: &str, fields: &[&str]) -> Result<String> {
+        let config = RgaConfig {
+            adapter_options: AdapterOptions {
+                journal_fields: fields.iter().map(|c| c.to_string()).collect(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        adapt_test_file_with_config(&JournalAdapter::new(), name, true, config).await
+    }
+
+    fn expected(kernel_fields: &str, nginx_fields: [&str; 2]) -> String {
+        format!(
+            "PREFIX:2024-03-01T10:15:30.123456Z nginx.service{}: Started server on port 443
+PREFIX:2024-03-01T10:15:31.623456Z kernel{kernel_fields}: Out of memory: Killed process 4242 (java) {}
+PREFIX:2024-03-01T10:16:30.123456Z nginx.service{}: upstream timed out
+PREFIX:2024-03-01T10:16:30.123456Z nginx.service{}: while reading response header
+PREFIX:
+",
+            nginx_fields[0],
+            "x".repeat(200),
+            nginx_fields[1],
+            nginx_fields[1],
+        )
+    }
+
+    #[tokio::test]
+    async fn xz_compressed() -> Result<()> {
+        assert_eq!(
+            adapt_file("hello.journal", &[]).await?,
+            expected("", ["", ""])
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn compact_zstd_compressed() -> Result<()> {
+        assert_eq!(
+            adapt_file("hello-compact.journal", &[]).await?,
+            expected("", ["", ""])
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn selected_fields() -> Result<()> {
+        assert_eq!(
+            adapt_file("hello.journal", &["_PID", "PRIORITY"]).await?,
+            expected(
+                " PRIORITY=3",
+                [" _PID=812 PRIORITY=6", " _PID=812 PRIORITY=4"]
+            )
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,272 @@
+use super::writing::WritingFileAdapter;
+use super::*;
+use anyhow::Result;
+use lazy_static::lazy_static;
+use serde::de::{self, DeserializeSeed, Deserializer, MapAccess, SeqAccess, Visitor};
+use std::fmt;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use tokio::io::AsyncWrite;
+use tokio_util::io::SyncIoBridge;
+
+static EXTENSIONS: &[&str] = &["json", "geojson", "ndjson", "jsonl"];
+static MIME_TYPES: &[&str] = &[
+    "application/json",
+    "application/geo+json",
+    "application/x-ndjson",
+];
+/// Files with one json document per line
+static LINE_EXTENSIONS: &[&str] = &["ndjson", "jsonl"];
+
+lazy_static! {
+    static ref METADATA: AdapterMeta = AdapterMeta {
+        name: "jsonflat".to_owned(),
+        version: 1,
+        description: "Flattens JSON and NDJSON files to one line per value with its full path, e.g. json.items[3].owner.email = \"x\".\nDisabled by default since rg can already search json files".to_owned(),
+        recurses: false,
+        fast_matchers: EXTENSIONS
+            .iter()
+            .map(|s| FastFileMatcher::FileExtension(s.to_string()))
+            .collect(),
+        slow_matchers: Some(
+            MIME_TYPES
+                .iter()
+                .map(|s| FileMatcher::MimeType(s.to_string()))
+                .collect()
+        ),
+        keep_fast_matchers_if_accurate: true,
+        disabled_by_default: true
+    };
+}
+
+#[derive(Default, Clone)]
+pub struct JsonFlatAdapter;
+
+impl JsonFlatAdapter {
+    pub fn new() -> Self {
+        Self
+    }
+}
+impl GetMetadata for JsonFlatAdapter {
+    fn metadata(&self) -> &AdapterMeta {
+        &METADATA
+    }
+}
+
+/// Appends an object key to the path, quoted if it isn't a plain identifier
+fn push_key(path: &mut String, key: &str) {
+    let is_identifier = key
+        .chars()
+        .next()
+        .is_some_and(|c| c.is_alphabetic() || c == '_' || c == '$')
+        && key
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '_' || c == '$');
+    if is_identifier {
+        path.push('.');
+        path.push_str(key);
+    } else {
+        path.push('[');
+        path.push_str(&serde_json::Value::from(key).to_string());
+        path.push(']');
+    }
+}
+
+/// Writes every leaf of a json value as `path = value` while it is being parsed,
+/// so that only the current path is kept in memory
+struct Flattener<'a, W> {
+    out: &'a mut W,
+    path: &'a mut String,
+}
+
+impl<W: Write> Flattener<'_, W> {
+    fn write_leaf<E: de::Error>(self, value: impl fmt::Display) -> Result<(), E> {
+        writeln!(self.out, "{} = {}", self.path, value).map_err(E::custom)
+    }
+}
+
+impl<'de, W: Write> DeserializeSeed<'de> for Flattener<'_, W> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_any(self)
+    }
+}
+
+impl<'de, W: Write> Visitor<'de> for Flattener<'_, W> {
+    type Value = ();
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("any json value")
+    }
+
+    fn visit_bool<E: de::Error>(self, v: bool) -> Result<(), E> {
+        self.write_leaf(v)
+    }
+
+    fn visit_i64<E: de::Error>(self, v: i64) -> Result<(), E> {
+        self.write_leaf(v)
+    }
+
+    fn visit_u64<E: de::Error>(self, v: u64) -> Result<(), E> {
+        self.write_leaf(v)
+    }
+
+    fn visit_f64<E: de::Error>(self, v: f64) -> Result<(), E> {
+        self.write_leaf(serde_json::Value::from(v))
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<(), E> {
+        // escaped, so that strings with newlines stay on one line
+        self.write_leaf(serde_json::Value::from(v))
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<(), E> {
+        self.write_leaf("null")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+        let len = self.path.len();
+        let mut i = 0;
+        loop {
+            self.path.push_str(&format!("[{i}]"));
+            let element = seq.next_element_seed(Flattener {
+                out: &mut *self.out,
+                path: &mut *self.path,
+            })?;
+            self.path.truncate(len);
+            if element.is_none() {
+                break;
+            }
+            i += 1;
+        }
+        if i == 0 {
+            self.write_leaf("[]")?;
+        }
+        Ok(())
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<(), A::Error> {
+        let len = self.path.len();
+        let mut empty = true;
+        while let Some(key) = map.next_key::<String>()? {
+            empty = false;
+            push_key(self.path, &key);
+            map.next_value_seed(Flattener {
+                out: &mut *self.out,
+                path: &mut *self.path,
+            })?;
+            self.path.truncate(len);
+        }
+        if empty {
+            self.write_leaf("{}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Flattens one json document, named `path`
+fn flatten_document<'de>(
+    deserializer: &mut serde_json::Deserializer<impl serde_json::de::Read<'de>>,
+    out: &mut impl Write,
+    mut path: String,
+) -> Result<()> {
+    Flattener {
+        out,
+        path: &mut path,
+    }
+    .deserialize(&mut *deserializer)?;
+    deserializer.end()?;
+    Ok(())
+}
+
+fn synchronous_dump_jsonflat(ai: AdaptInfo, out: impl Write) -> Result<()> {
+    let is_lines = ai
+        .filepath_hint
+        .extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| LINE_EXTENSIONS.contains(&e.to_lowercase().as_str()));
+    let mut inp = BufReader::new(SyncIoBridge::new(ai.inp));
+    let mut out = BufWriter::new(out);
+    if is_lines {
+        // each document is named by its index, skipping empty lines
+        let mut line = String::new();
+        let mut index = 0;
+        while inp.read_line(&mut line)? > 0 {
+            if !line.trim().is_empty() {
+                let mut deserializer = serde_json::Deserializer::from_str(&line);
+                flatten_document(&mut deserializer, &mut out, format!("json[{index}]"))
+                    .with_context(|| format!("in document {index}"))?;
+                index += 1;
+            }
+            line.clear();
+        }
+    } else {
+        let mut deserializer = serde_json::Deserializer::from_reader(inp);
+        flatten_document(&mut deserializer, &mut out, "json".to_owned())?;
+    }
+    out.flush()?;
+    Ok(())
+}
+
+#[async_trait]
+impl WritingFileAdapter for JsonFlatAdapter {
+    async fn adapt_write(
+        ai: AdaptInfo,
+        _detection_reason: &FileMatcher,
+        oup: Pin<Box<dyn AsyncWrite + Send>>,
+    ) -> Result<()> {
+        let oup_sync = SyncIoBridge::new(oup);
+        tokio::task::spawn_blocking(|| synchronous_dump_jsonflat(ai, oup_sync))
+            .await?
+            .context("in synchronous jsonflat task")?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{preproc::loop_adapt, test_utils::*};
+    use pretty_assertions::assert_eq;
+
+    #[tokio::test]
+    async fn json() -> Result<()> {
+        assert_eq!(
+            adapt_test_file(&JsonFlatAdapter::new(), "hello.json", true).await?,
+            r#"PREFIX:json.name = "rga"
+PREFIX:json.version = 1.5
+PREFIX:json.items[0].id = 1
+PREFIX:json.items[0].owner.email = "alice@example.com"
+PREFIX:json.items[0].tags = []
+PREFIX:json.items[1].id = -2
+PREFIX:json.items[1].owner = null
+PREFIX:json.items[1].tags[0] = "multi\nline"
+PREFIX:json.items[1].tags[1] = true
+PREFIX:json["content-type"] = "text/plain"
+PREFIX:json.extra = {}
+PREFIX:
+"#
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn ndjson_gz() -> Result<()> {
+        // streamed through the decompress adapter
+        let filepath = test_data_dir().join("hello.ndjson.gz");
+        let (mut a, d) = simple_fs_adapt_info(&filepath).await?;
+        a.config.adapters = vec!["+jsonflat".to_owned()];
+        let o = adapted_to_vec(loop_adapt(&decompress::DecompressAdapter, d, a).await?).await?;
+        assert_eq!(
+            String::from_utf8(o)?,
+            r#"PREFIX:json[0].level = "info"
+PREFIX:json[0].msg = "started"
+PREFIX:json[1].level = "error"
+PREFIX:json[1].msg = "disk full"
+PREFIX:json[1].ctx.free = 0
+PREFIX:
+"#
+        );
+        Ok(())
+    }
+}
@@ -3,18 +3,20 @@ use super::*;
 use anyhow::Result;
 use async_stream::stream;
 use lazy_static::lazy_static;
+use mailparse::MailHeaderMap;
 use mime2ext::mime2ext;
-use regex::bytes::Regex;
-use tokio::io::AsyncReadExt;
+use tokio::io::{AsyncBufReadExt, BufReader};
 
-use std::{collections::VecDeque, io::Cursor};
+use std::{collections::VecDeque, io::Cursor, path::Path};
 
 static EXTENSIONS: &[&str] = &["mbox", "mbx", "eml"];
 static MIME_TYPES: &[&str] = &["application/mbox", "message/rfc822"];
+/// The headers that are yielded as a `headers.txt` part of each mail
+static HEADERS: &[&str] = &["From", "To", "Cc", "Subject", "Date", "Message-ID"];
 lazy_static! {
     static ref METADATA: AdapterMeta = AdapterMeta {
         name: "mail".to_owned(),
-        version: 1,
+        version: 2,
         description:
             "Reads mailbox/mail files and runs extractors on the contents and attachments."
                 .to_owned(),
@@ -32,7 +34,6 @@ lazy_static! {
         disabled_by_default: true,
         keep_fast_matchers_if_accurate: true
     };
-    static ref FROM_REGEX: Regex = Regex::new("\r?\nFrom [^\n]+\n").unwrap();
 }
 #[derive(Default)]
 pub struct MboxAdapter;
@@ -48,6 +49,93 @@ impl GetMetadata for MboxAdapter {
     }
 }
 
+/// Splits a mail into its headers and its leaf MIME parts, each yielded as a file within the mailbox.
+///
+/// `index` is the 1-based position of the mail in the mailbox.
+fn mail_parts(
+    mail_content: &[u8],
+    index: usize,
+    filepath_hint: &Path,
+    line_prefix: &str,
+    archive_recursion_depth: i32,
+    config: &RgaConfig,
+    postprocess: bool,
+) -> Vec<AdaptInfo> {
+    let Ok(mail) = mailparse::parse_mail(mail_content) else {
+        return vec![];
+    };
+    // so matches in different mails of a mailbox can be told apart
+    let line_prefix = match mail.headers.get_first_value("Subject") {
+        Some(subject) => format!(
+            "{line_prefix}#{index} {}: ",
+            subject.split_whitespace().collect::<Vec<_>>().join(" ")
+        ),
+        None => format!("{line_prefix}#{index}: "),
+    };
+    // header values are decoded from RFC 2047 encoded-words by mailparse
+    let headers: String = HEADERS
+        .iter()
+        .flat_map(|name| {
+            mail.headers
+                .get_all_values(name)
+                .into_iter()
+                .map(move |value| format!("{name}: {value}\n"))
+        })
+        .collect();
+    let mut ais = vec![AdaptInfo {
+        filepath_hint: filepath_hint.join("headers.txt"),
+        is_real_file: false,
+        archive_recursion_depth: archive_recursion_depth + 1,
+        inp: Box::pin(Cursor::new(headers.into_bytes())),
+        line_prefix: line_prefix.clone(),
+        config: config.clone(),
+        postprocess,
+    }];
+    let mut todos = VecDeque::new();
+    todos.push_back(mail);
+
+    while let Some(mail) = todos.pop_front() {
+        let mut path = filepath_hint.to_owned();
+        let filename = mail
+            .get_content_disposition()
+            .params
+            .get("filename")
+            .cloned();
+        match &*mail.ctype.mimetype {
+            x if x.starts_with("multipart/") => {
+                todos.extend(mail.subparts);
+                continue;
+            }
+            mime => {
+                if let Some(name) = filename {
+                    path.push(name);
+                } else if let Some(extension) = mime2ext(mime) {
+                    path.push(format!("data.{extension}"));
+                } else {
+                    path.push("data");
+                }
+            }
+        }
+
+        let mut config = config.clone();
+        config.accurate = true;
+
+        let Ok(raw_body) = mail.get_body_raw() else {
+            continue;
+        };
+        ais.push(AdaptInfo {
+            filepath_hint: path,
+            is_real_file: false,
+            archive_recursion_depth: archive_recursion_depth + 1,
+            inp: Box::pin(Cursor::new(raw_body)),
+            line_prefix: line_prefix.clone(),
+            config,
+            postprocess,
+        });
+    }
+    ais
+}
+
 #[async_trait]
 impl FileAdapter for MboxAdapter {
     async fn adapt(
@@ -57,7 +145,7 @@ impl FileAdapter for MboxAdapter {
     ) -> Result<AdaptedFilesIterBox> {
         let AdaptInfo {
             filepath_hint,
-            mut inp,
+            inp,
             line_prefix,
             archive_recursion_depth,
             config,
@@ -65,63 +153,39 @@ impl FileAdapter for MboxAdapter {
             ..
         } = ai;
 
-        let mut content = Vec::new();
         let s = stream! {
-            inp.read_to_end(&mut content).await?;
-
-            let mut ais = vec![];
-            for mail_bytes in FROM_REGEX.splitn(&content, usize::MAX) {
-                let mail_content = mail_bytes.splitn(2, |x| *x == b'\n').nth(1).unwrap();
-                let mail = mailparse::parse_mail(mail_content);
-                if mail.is_err() {
-                    continue;
-                }
-                let mail = mail.unwrap();
-
-                let mut todos = VecDeque::new();
-                todos.push_back(mail);
-
-                while let Some(mail) = todos.pop_front() {
-                let mut path = filepath_hint.clone();
-                let filename = mail.get_content_disposition().params.get("filename").cloned();
-                match &*mail.ctype.mimetype {
-                    x if x.starts_with("multipart/") => {
-                        todos.extend(mail.subparts);
-                        continue;
-                    }
-                    mime => {
-                        if let Some(name) = filename {
-                            path.push(name);
-                        } else if let Some(extension) = mime2ext(mime) {
-                            path.push(format!("data.{extension}"));
-                        } else {
-                            path.push("data");
+            // mailboxes can be huge, so only one message is kept in memory at a time
+            let mut inp = BufReader::new(inp);
+            let mut mail_content = Vec::new();
+            let mut line = Vec::new();
+            let mut index = 0;
+            loop {
+                line.clear();
+                let eof = inp.read_until(b'\n', &mut line).await? == 0;
+                // every message in an mbox starts with a "From " line, single mail files (.eml) have none
+                if eof || line.starts_with(b"From ") {
+                    if !mail_content.is_empty() {
+                        index += 1;
+                        for ai in mail_parts(
+                            &mail_content,
+                            index,
+                            &filepath_hint,
+                            &line_prefix,
+                            archive_recursion_depth,
+                            &config,
+                            postprocess,
+                        ) {
+                            yield Ok(ai);
                         }
+                        mail_content.clear();
                     }
-                }
-
-                let mut config = config.clone();
-                config.accurate = true;
-
-                let raw_body = mail.get_body_raw();
-                if raw_body.is_err() {
-                    continue;
-                }
-                let ai2: AdaptInfo = AdaptInfo {
-                    filepath_hint: path,
-                    is_real_file: false,
-                    archive_recursion_depth: archive_recursion_depth + 1,
-                    inp: Box::pin(Cursor::new(raw_body.unwrap())),
-                    line_prefix: line_prefix.to_string(),
-                    config,
-                    postprocess,
-                };
-                ais.push(ai2);
+                    if eof {
+                        break;
+                    }
+                } else {
+                    mail_content.extend_from_slice(&line);
                 }
             }
-            for a in ais {
-                yield(Ok(a));
-            }
         };
         Ok(Box::pin(s))
     }
@@ -134,6 +198,7 @@ mod tests {
     use crate::test_utils::*;
     use pretty_assertions::assert_eq;
     use tokio::fs::File;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
     use tokio_stream::StreamExt;
 
     #[tokio::test]
@@ -158,14 +223,23 @@ mod tests {
                 .to_str()
                 .unwrap()
             {
+                "headers.txt" => {
+                    assert!(String::from_utf8(buf)?.contains(
+                        "To: KeYProject/key <key@noreply.github.com>\nSubject: Re: [KeYProject/key] Fix more UI bugs (PR #3232)\n"
+                    ));
+                }
                 "data.txt" | "data.html" => {
                     assert!(String::from_utf8(buf)?.contains("Thank you for your contribution"));
                 }
                 x => panic!("unexpected filename {x:?}"),
             }
+            assert_eq!(
+                "PREFIX:#1 Re: [KeYProject/key] Fix more UI bugs (PR #3232): ",
+                file.line_prefix
+            );
             count += 1;
         }
-        assert_eq!(2, count);
+        assert_eq!(3, count);
         Ok(())
     }
 
@@ -180,6 +254,9 @@ mod tests {
         let mut count = 0;
         while let Some(file) = r.next().await {
             let mut file = file?;
+            if file.filepath_hint.ends_with("headers.txt") {
+                continue;
+            }
             assert_eq!(
                 "data.html",
                 file.filepath_hint
@@ -188,18 +265,64 @@ mod tests {
                     .unwrap()
                     .as_os_str()
             );
+            count += 1;
+            assert_eq!(
+                format!("PREFIX:#{count} From encoding test: "),
+                file.line_prefix
+            );
             let mut buf = Vec::new();
             file.inp.read_to_end(&mut buf).await?;
             assert_eq!(
                 "<html>\r\n  <head>\r\n    <meta http-equiv=\"content-type\" content=\"text/html; charset=UTF-8\">\r\n  </head>\r\n  <body>\r\n    <p>&gt;From</p>\r\n    <p>Another word &gt;From<br>\r\n    </p>\r\n  </body>\r\n</html>",
                 String::from_utf8(buf)?.trim()
             );
-            count += 1;
         }
         assert_eq!(3, count);
         Ok(())
     }
 
+    #[tokio::test]
+    async fn mbox_streaming() -> Result<()> {
+        let adapter = MboxAdapter;
+
+        let (mut w, r) = tokio::io::duplex(1024);
+        let (a, d) = simple_adapt_info(&PathBuf::from("test.mbox"), Box::pin(r));
+        let mut r = adapter.adapt(a, &d).await?;
+        w.write_all(b"From a\nSubject: =?utf-8?q?caf=C3=A9?=\n\nfirst body\nFrom b\n")
+            .await?;
+        let mut next = async || -> Result<(String, String)> {
+            let mut file = r.next().await.unwrap()?;
+            let mut buf = String::new();
+            file.inp.read_to_string(&mut buf).await?;
+            Ok((file.line_prefix, buf))
+        };
+        // the first message is yielded before the rest of the mailbox has been written
+        assert_eq!(
+            ("PREFIX:#1 café: ".to_owned(), "Subject: café\n".to_owned()),
+            next().await?
+        );
+        assert_eq!(
+            ("PREFIX:#1 café: ".to_owned(), "first body\n".to_owned()),
+            next().await?
+        );
+        w.write_all(b"To: someone@example.com\n\nsecond body\n")
+            .await?;
+        drop(w);
+        assert_eq!(
+            (
+                "PREFIX:#2: ".to_owned(),
+                "To: someone@example.com\n".to_owned()
+            ),
+            next().await?
+        );
+        assert_eq!(
+            ("PREFIX:#2: ".to_owned(), "second body\n".to_owned()),
+            next().await?
+        );
+        assert!(r.next().await.is_none());
+        Ok(())
+    }
+
     #[tokio::test]
     async fn mbox_attachment() -> Result<()> {
         init_logging();
@@ -224,15 +347,26 @@ mod tests {
             let mut buf = Vec::new();
             file.inp.read_to_end(&mut buf).await?;
             match path {
+                "headers.txt" => {
+                    assert_eq!(
+                        "PREFIX:#1 Subject line: From: Arne Keller <uskyk@student.kit.edu>
+PREFIX:#1 Subject line: To: <recipient@gmail.com>
+PREFIX:#1 Subject line: Subject: Subject line
+PREFIX:#1 Subject line: Date: Mon, 31 Jul 2023 15:15:41 +0200
+PREFIX:#1 Subject line: Message-ID: <68b5f6c4-8de4-02e9-fd09-8157d8ccd29b@student.kit.edu>
+PREFIX:#1 Subject line: \n",
+                        String::from_utf8(buf).unwrap_or("err".to_owned())
+                    );
+                }
                 "data.html.txt" => {
                     assert_eq!(
-                        "PREFIX:regular text\nPREFIX:\n",
+                        "PREFIX:#1 Subject line: regular text\nPREFIX:#1 Subject line: \n",
                         String::from_utf8(buf).unwrap_or("err".to_owned())
                     );
                 }
                 "short.pdf.txt" => {
                     assert_eq!(
-                        "PREFIX:Page 1: hello world\nPREFIX:Page 1: this is just a test.\nPREFIX:Page 1: \nPREFIX:Page 1: 1\nPREFIX:Page 1: \nPREFIX:Page 1: \n",
+                        short_pdf_text("PREFIX:#1 Subject line: "),
                         String::from_utf8(buf).unwrap_or("err".to_owned())
                     );
                 }
@@ -242,7 +376,7 @@ mod tests {
             }
             count += 1;
         }
-        assert_eq!(2, count); // one message + one attachment
+        assert_eq!(3, count); // headers + one message + one attachment
         Ok(())
     }
 }
@@ -0,0 +1,367 @@
+use super::writing::WritingFileAdapter;
+use super::xml::write_lines;
+use super::*;
+use anyhow::{Result, bail};
+use lazy_static::lazy_static;
+use std::collections::BTreeMap;
+use std::io::{BufWriter, Cursor, Read, Write};
+use tokio::io::AsyncWrite;
+use tokio_util::io::SyncIoBridge;
+
+// .xls files are read by the spreadsheet adapter
+static EXTENSIONS: &[&str] = &["doc", "ppt", "pps"];
+static MIME_TYPES: &[&str] = &["application/msword", "application/vnd.ms-powerpoint"];
+
+lazy_static! {
+    static ref METADATA: AdapterMeta = AdapterMeta {
+        name: "msoffice".to_owned(),
+        version: 1,
+        description: "Extracts the text of legacy binary Word (.doc) and PowerPoint (.ppt) files, with the slide number for each line of a presentation".to_owned(),
+        recurses: false,
+        fast_matchers: EXTENSIONS
+            .iter()
+            .map(|s| FastFileMatcher::FileExtension(s.to_string()))
+            .collect(),
+        slow_matchers: Some(
+            MIME_TYPES
+                .iter()
+                .map(|s| FileMatcher::MimeType(s.to_string()))
+                .collect()
+        ),
+        keep_fast_matchers_if_accurate: true,
+        disabled_by_default: false
+    };
+}
+
+#[derive(Default, Clone)]
+pub struct MsOfficeAdapter;
+
+impl MsOfficeAdapter {
+    pub fn new() -> Self {
+        Self
+    }
+}
+impl GetMetadata for MsOfficeAdapter {
+    fn metadata(&self) -> &AdapterMeta {
+        &METADATA
+    }
+}
+
+fn u16_at(data: &[u8], pos: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(data.get(pos..pos + 2)?.try_into().ok()?))
+}
+
+fn u32_at(data: &[u8], pos: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(data.get(pos..pos + 4)?.try_into().ok()?))
+}
+
+fn decode_utf16(data: &[u8]) -> String {
+    let units: Vec<u16> = data
+        .chunks_exact(2)
+        .map(|c| u16::from_le_bytes([c[0], c[1]]))
+        .collect();
+    String::from_utf16_lossy(&units)
+}
+
+fn read_stream<F: Read + std::io::Seek>(
+    cfb: &mut cfb::CompoundFile<F>,
+    name: &str,
+) -> Result<Vec<u8>> {
+    let mut data = Vec::new();
+    cfb.open_stream(name)
+        .with_context(|| format!("opening stream {name}"))?
+        .read_to_end(&mut data)?;
+    Ok(data)
+}
+
+/// Replaces the special characters of Word documents and removes field codes, see [MS-DOC] 2.8.25
+fn clean_word_text(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    // for each nested field, whether we are still in its code (before the separator)
+    let mut fields: Vec<bool> = Vec::new();
+    for c in text.chars() {
+        match c {
+            '\x13' => fields.push(true),
+            '\x14' => {
+                if let Some(in_code) = fields.last_mut() {
+                    *in_code = false;
+                }
+            }
+            '\x15' => {
+                fields.pop();
+            }
+            _ if fields.iter().any(|&in_code| in_code) => {}
+            // paragraph, line, page and section breaks
+            '\r' | '\x0b' | '\x0c' => result.push('\n'),
+            // end of a table cell or row
+            '\x07' => result.push('\t'),
+            '\x1e' => result.push('-'),
+            '\t' => result.push('\t'),
+            // e.g. optional hyphens, pictures and footnote marks
+            c if c < ' ' => {}
+            c => result.push(c),
+        }
+    }
+    result
+}
+
+/// Reads the text of a Word 97-2003 document from its piece table, see [MS-DOC] 2.4.1
+fn word_text<F: Read + std::io::Seek>(cfb: &mut cfb::CompoundFile<F>) -> Result<String> {
+    let word = read_stream(cfb, "WordDocument")?;
+    if u16_at(&word, 0) != Some(0xA5EC) {
+        bail!("not a Word 97-2003 document");
+    }
+    let flags = u16_at(&word, 0x0A).context("truncated file information block")?;
+    if flags & 0x0100 != 0 {
+        bail!("the document is encrypted");
+    }
+    let table = read_stream(
+        cfb,
+        if flags & 0x0200 != 0 {
+            "1Table"
+        } else {
+            "0Table"
+        },
+    )?;
+    // the fixed-size FibBase is followed by three variable-size arrays
+    let csw = u16_at(&word, 32).context("truncated file information block")? as usize;
+    let rg_lw = 32 + 2 + csw * 2;
+    let cslw = u16_at(&word, rg_lw).context("truncated file information block")? as usize;
+    let rg_fc_lcb = rg_lw + 2 + cslw * 4 + 2;
+    // fcClx and lcbClx are the 34th pair in FibRgFcLcb97
+    let fc_clx = u32_at(&word, rg_fc_lcb + 33 * 8).context("no piece table")? as usize;
+    let lcb_clx = u32_at(&word, rg_fc_lcb + 33 * 8 + 4).context("no piece table")? as usize;
+    let clx = table
+        .get(fc_clx..fc_clx + lcb_clx)
+        .context("piece table is out of bounds")?;
+    // skip the formatting (Prc) entries before the piece table (Pcdt)
+    let mut pos = 0;
+    while clx.get(pos) == Some(&0x01) {
+        pos += 3 + u16_at(clx, pos + 1).context("truncated piece table")? as usize;
+    }
+    if clx.get(pos) != Some(&0x02) {
+        bail!("invalid piece table");
+    }
+    let lcb = u32_at(clx, pos + 1).context("truncated piece table")? as usize;
+    let plc = clx
+        .get(pos + 5..pos + 5 + lcb)
+        .context("truncated piece table")?;
+    // n + 1 character positions followed by n 8-byte piece descriptors
+    let pieces = lcb.saturating_sub(4) / 12;
+    let mut text = String::new();
+    for i in 0..pieces {
+        let (Some(start), Some(end), Some(fc)) = (
+            u32_at(plc, i * 4),
+            u32_at(plc, (i + 1) * 4),
+            u32_at(plc, (pieces + 1) * 4 + i * 8 + 2),
+        ) else {
+            bail!("truncated piece table");
+        };
+        let chars = end.saturating_sub(start) as usize;
+        if fc & 0x4000_0000 != 0 {
+            // 8-bit characters
+            let offset = (fc & !0x4000_0000) as usize / 2;
+            let bytes = word
+                .get(offset..offset + chars)
+                .context("piece is out of bounds")?;
+            text.push_str(
+                &encoding_rs::WINDOWS_1252
+                    .decode_without_bom_handling(bytes)
+                    .0,
+            );
+        } else {
+            let offset = fc as usize;
+            let bytes = word
+                .get(offset..offset + chars * 2)
+                .context("piece is out of bounds")?;
+            text.push_str(&decode_utf16(bytes));
+        }
+    }
+    Ok(clean_word_text(&text))
+}
+
+// PowerPoint record types, see [MS-PPT] 2.13.24
+const RT_SLIDE: u16 = 0x03EE;
+const RT_MAIN_MASTER: u16 = 0x03F8;
+const RT_SLIDE_PERSIST_ATOM: u16 = 0x03F3;
+const RT_SLIDE_LIST_WITH_TEXT: u16 = 0x0FF0;
+const RT_TEXT_CHARS_ATOM: u16 = 0x0FA0;
+const RT_TEXT_BYTES_ATOM: u16 = 0x0FA8;
+
+/// Real documents only nest records a few levels deep (e.g. for groups of shapes)
+const MAX_RECORD_DEPTH: usize = 32;
+
+/// The slide numbers of the text in a PowerPoint document
+#[derive(Default)]
+struct SlideCounter {
+    /// slides with text boxes, in the order of the document
+    slides: usize,
+    /// slides with placeholder text (titles, bullet points) in the slide list
+    listed_slides: usize,
+}
+
+/// The text of a PowerPoint document by slide, text outside of slides has no slide number
+type SlideTexts = BTreeMap<Option<usize>, String>;
+
+/// Collects the text atoms in the records of a PowerPoint document, see [MS-PPT] 2.3
+///
+/// The placeholder text of a slide is stored in the slide list, separately from its text boxes.
+fn read_ppt_records(
+    texts: &mut SlideTexts,
+    data: &[u8],
+    counter: &mut SlideCounter,
+    slide: Option<usize>,
+    in_slide_list: bool,
+    depth: usize,
+) -> Result<()> {
+    if depth > MAX_RECORD_DEPTH {
+        bail!("records are nested more than {MAX_RECORD_DEPTH} levels deep");
+    }
+    let mut pos = 0;
+    let mut slide = slide;
+    while let (Some(ver_instance), Some(rec_type), Some(len)) = (
+        u16_at(data, pos),
+        u16_at(data, pos + 2),
+        u32_at(data, pos + 4),
+    ) {
+        let body = data
+            .get(pos + 8..(pos + 8).saturating_add(len as usize))
+            .unwrap_or(&data[(pos + 8).min(data.len())..]);
+        pos += 8 + body.len();
+        let is_container = ver_instance & 0x000F == 0x000F;
+        let instance = ver_instance >> 4;
+        let mut push_text = |text: &str| {
+            let slide_text = texts.entry(slide).or_default();
+            slide_text.push_str(&text.replace(['\r', '\x0b'], "\n"));
+            slide_text.push('\n');
+        };
+        match rec_type {
+            // the master slides only contain placeholder text like "Click to edit Master title style"
+            RT_MAIN_MASTER => {}
+            RT_SLIDE_LIST_WITH_TEXT if instance == 1 => {}
+            RT_SLIDE_LIST_WITH_TEXT => {
+                read_ppt_records(texts, body, counter, None, instance == 0, depth + 1)?
+            }
+            RT_SLIDE => {
+                counter.slides += 1;
+                read_ppt_records(texts, body, counter, Some(counter.slides), false, depth + 1)?;
+            }
+            RT_SLIDE_PERSIST_ATOM if in_slide_list => {
+                counter.listed_slides += 1;
+                slide = Some(counter.listed_slides);
+            }
+            RT_TEXT_CHARS_ATOM => push_text(&decode_utf16(body)),
+            RT_TEXT_BYTES_ATOM => push_text(
+                &encoding_rs::WINDOWS_1252
+                    .decode_without_bom_handling(body)
+                    .0,
+            ),
+            _ if is_container => {
+                read_ppt_records(texts, body, counter, slide, in_slide_list, depth + 1)?
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+fn synchronous_dump_msoffice(ai: AdaptInfo, out: impl Write) -> Result<()> {
+    // the streams of a compound file are scattered over sectors listed in its allocation table
+    let mut data = Vec::new();
+    SyncIoBridge::new(ai.inp).read_to_end(&mut data)?;
+    let mut cfb = cfb::CompoundFile::open(Cursor::new(data)).context("opening compound file")?;
+    let mut out = BufWriter::new(out);
+    if cfb.is_stream("WordDocument") {
+        write_lines(&mut out, "", &word_text(&mut cfb)?)?;
+    } else if cfb.is_stream("PowerPoint Document") {
+        let document = read_stream(&mut cfb, "PowerPoint Document")?;
+        let mut texts = SlideTexts::new();
+        read_ppt_records(
+            &mut texts,
+            &document,
+            &mut SlideCounter::default(),
+            None,
+            false,
+            0,
+        )?;
+        for (slide, text) in texts {
+            let prefix = slide.map(|s| format!("slide {s}: ")).unwrap_or_default();
+            write_lines(&mut out, &prefix, &text)?;
+        }
+    } else {
+        bail!("not a Word or PowerPoint file");
+    }
+    out.flush()?;
+    Ok(())
+}
+
+#[async_trait]
+impl WritingFileAdapter for MsOfficeAdapter {
+    async fn adapt_write(
+        ai: AdaptInfo,
+        _detection_reason: &FileMatcher,
+        oup: Pin<Box<dyn AsyncWrite + Send>>,
+    ) -> Result<()> {
+        let oup_sync = SyncIoBridge::new(oup);
+        tokio::task::spawn_blocking(|| synchronous_dump_msoffice(ai, oup_sync))
+            .await?
+            .context("in synchronous msoffice task")?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use pretty_assertions::assert_eq;
+
+    #[tokio::test]
+    async fn doc() -> Result<()> {
+        assert_eq!(
+            adapt_test_file(&MsOfficeAdapter::new(), "hello.doc", true).await?,
+            "PREFIX:Hello world!
+PREFIX:Visit example.com for more.
+PREFIX:Caf\u{e9}\tna\u{ef}ve
+PREFIX:
+"
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn ppt() -> Result<()> {
+        assert_eq!(
+            adapt_test_file(&MsOfficeAdapter::new(), "hello.ppt", true).await?,
+            "PREFIX:slide 1: Quarterly results
+PREFIX:slide 1: Revenue is up
+PREFIX:slide 1: Text box on the first slide
+PREFIX:slide 2: Thank you
+PREFIX:
+"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn deeply_nested_records() {
+        // container headers (version 0xF) that each contain the next one
+        let data: Vec<u8> = (0..10_000u32)
+            .rev()
+            .flat_map(|i| {
+                let mut header = vec![0x0f, 0x00, 0x00, 0x10];
+                header.extend_from_slice(&(i * 8).to_le_bytes());
+                header
+            })
+            .collect();
+        let res = read_ppt_records(
+            &mut SlideTexts::new(),
+            &data,
+            &mut SlideCounter::default(),
+            None,
+            false,
+            0,
+        );
+        assert!(res.is_err());
+    }
+}
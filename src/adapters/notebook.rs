@@ -0,0 +1,248 @@
+use super::xml::write_lines;
+use super::*;
+use anyhow::Result;
+use async_stream::stream;
+use base64::Engine;
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde_json::Value;
+use std::io::Cursor;
+use tokio::io::AsyncReadExt;
+
+static EXTENSIONS: &[&str] = &["ipynb"];
+static MIME_TYPES: &[&str] = &["application/x-ipynb+json"];
+/// The image outputs that are passed on with --rga-notebook-images, by preference
+static IMAGE_TYPES: &[(&str, &str)] = &[
+    ("image/png", "png"),
+    ("image/jpeg", "jpg"),
+    ("image/gif", "gif"),
+];
+
+lazy_static! {
+    static ref METADATA: AdapterMeta = AdapterMeta {
+        name: "notebook".to_owned(),
+        version: 1,
+        description: "Outputs the sources, text outputs and error tracebacks of Jupyter notebook cells, prefixed with `cell 3 [code]: `.\nWith --rga-notebook-images, image outputs are passed on to the image adapters as well.".to_owned(),
+        recurses: true,
+        fast_matchers: EXTENSIONS
+            .iter()
+            .map(|s| FastFileMatcher::FileExtension(s.to_string()))
+            .collect(),
+        slow_matchers: Some(
+            MIME_TYPES
+                .iter()
+                .map(|s| FileMatcher::MimeType(s.to_string()))
+                .collect()
+        ),
+        keep_fast_matchers_if_accurate: true,
+        disabled_by_default: false
+    };
+    /// Colors and cursor movements in tracebacks
+    static ref ANSI_ESCAPE: Regex = Regex::new(r"\x1b\[[0-9;]*[A-Za-z]").unwrap();
+}
+
+#[derive(Default, Clone)]
+pub struct NotebookAdapter;
+
+impl NotebookAdapter {
+    pub fn new() -> Self {
+        Self
+    }
+}
+impl GetMetadata for NotebookAdapter {
+    fn metadata(&self) -> &AdapterMeta {
+        &METADATA
+    }
+}
+
+/// Multiline strings in notebooks are either a string or a list of lines
+fn multiline(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        Value::Array(lines) => lines.iter().filter_map(Value::as_str).collect(),
+        _ => String::new(),
+    }
+}
+
+/// An image output of a cell
+struct Image {
+    cell: usize,
+    prefix: String,
+    extension: &'static str,
+    data: Vec<u8>,
+}
+
+/// Writes the text of all cells and collects their image outputs
+fn dump_notebook(notebook: &Value, out: &mut Vec<u8>, images: &mut Vec<Image>) -> Result<()> {
+    let cells: Vec<&Value> = match notebook.get("worksheets") {
+        // nbformat 3
+        Some(Value::Array(worksheets)) => worksheets
+            .iter()
+            .filter_map(|w| w.get("cells")?.as_array())
+            .flatten()
+            .collect(),
+        _ => notebook
+            .get("cells")
+            .and_then(Value::as_array)
+            .context("notebook has no cells")?
+            .iter()
+            .collect(),
+    };
+    for (i, cell) in cells.into_iter().enumerate() {
+        let cell_type = cell.get("cell_type").and_then(Value::as_str).unwrap_or("");
+        let prefix = format!("cell {} [{}]: ", i + 1, cell_type);
+        let source = cell.get("source").or_else(|| cell.get("input"));
+        write_lines(out, &prefix, &multiline(source.unwrap_or(&Value::Null)))?;
+        let outputs = cell.get("outputs").and_then(Value::as_array);
+        for output in outputs.into_iter().flatten() {
+            match output.get("output_type").and_then(Value::as_str) {
+                Some("stream") => write_lines(out, &prefix, &multiline(&output["text"]))?,
+                Some("error" | "pyerr") => {
+                    let traceback = output["traceback"].as_array().map(|lines| {
+                        lines
+                            .iter()
+                            .filter_map(Value::as_str)
+                            .collect::<Vec<_>>()
+                            .join("\n")
+                    });
+                    let traceback = match traceback {
+                        Some(traceback) if !traceback.is_empty() => traceback,
+                        _ => format!(
+                            "{}: {}",
+                            output["ename"].as_str().unwrap_or(""),
+                            output["evalue"].as_str().unwrap_or("")
+                        ),
+                    };
+                    write_lines(out, &prefix, &ANSI_ESCAPE.replace_all(&traceback, ""))?;
+                }
+                // execute_result, display_data and pyout in nbformat 3
+                _ => {
+                    let data = output.get("data").unwrap_or(output);
+                    if let Some(text) = data.get("text/plain").or_else(|| data.get("text")) {
+                        write_lines(out, &prefix, &multiline(text))?;
+                    }
+                    let image = IMAGE_TYPES
+                        .iter()
+                        .find_map(|(mime, ext)| Some((data.get(*mime)?, *ext)));
+                    if let Some((image, extension)) = image {
+                        let image = multiline(image).replace(['\n', '\r'], "");
+                        match base64::engine::general_purpose::STANDARD.decode(image) {
+                            Ok(data) => images.push(Image {
+                                cell: i + 1,
+                                prefix: prefix.clone(),
+                                extension,
+                                data,
+                            }),
+                            Err(e) => debug!("invalid image output in cell {}: {e}", i + 1),
+                        }
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+#[async_trait]
+impl FileAdapter for NotebookAdapter {
+    async fn adapt(
+        &self,
+        ai: AdaptInfo,
+        _detection_reason: &FileMatcher,
+    ) -> Result<AdaptedFilesIterBox> {
+        let AdaptInfo {
+            filepath_hint,
+            mut inp,
+            line_prefix,
+            archive_recursion_depth,
+            config,
+            postprocess,
+            ..
+        } = ai;
+        let s = stream! {
+            let mut content = Vec::new();
+            inp.read_to_end(&mut content).await?;
+            let notebook: Value = serde_json::from_slice(&content)
+                .with_context(|| format!("parsing notebook {}", filepath_hint.display()))?;
+            let mut text = Vec::new();
+            let mut images = Vec::new();
+            dump_notebook(&notebook, &mut text, &mut images)?;
+            yield Ok(AdaptInfo {
+                filepath_hint: PathBuf::from(format!("{}.txt", filepath_hint.to_string_lossy())),
+                is_real_file: false,
+                archive_recursion_depth,
+                inp: Box::pin(Cursor::new(text)),
+                line_prefix: line_prefix.clone(),
+                config: config.clone(),
+                postprocess,
+            });
+            if config.adapter_options.notebook_images {
+                let mut counts = std::collections::HashMap::new();
+                for image in images {
+                    let count = counts.entry(image.cell).or_insert(0);
+                    *count += 1;
+                    yield Ok(AdaptInfo {
+                        filepath_hint: filepath_hint
+                            .join(format!("cell{}-image{}.{}", image.cell, count, image.extension)),
+                        is_real_file: false,
+                        archive_recursion_depth: archive_recursion_depth + 1,
+                        inp: Box::pin(Cursor::new(image.data)),
+                        line_prefix: format!("{}{}", line_prefix, image.prefix),
+                        config: config.clone(),
+                        postprocess,
+                    });
+                }
+            }
+        };
+        Ok(Box::pin(s))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{preproc::loop_adapt, test_utils::*};
+    use pretty_assertions::assert_eq;
+    use tokio_stream::StreamExt;
+
+    #[tokio::test]
+    async fn cells() -> Result<()> {
+        let filepath = test_data_dir().join("hello.ipynb");
+        let (a, d) = simple_fs_adapt_info(&filepath).await?;
+        let o = adapted_to_vec(loop_adapt(&NotebookAdapter::new(), d, a).await?).await?;
+        assert_eq!(
+            String::from_utf8(o)?,
+            "PREFIX:cell 1 [markdown]: # Analysis
+PREFIX:cell 1 [markdown]: Some *notes*.
+PREFIX:cell 2 [code]: import math
+PREFIX:cell 2 [code]: print(math.pi)
+PREFIX:cell 2 [code]: 3.141592653589793
+PREFIX:cell 3 [code]: 1 / 0
+PREFIX:cell 3 [code]: ---------------------------------------------------------------------------
+PREFIX:cell 3 [code]: ZeroDivisionError                         Traceback (most recent call last)
+PREFIX:cell 3 [code]: ZeroDivisionError: division by zero
+PREFIX:cell 4 [code]: plot()
+PREFIX:cell 4 [code]: <Figure size 640x480 with 1 Axes>
+PREFIX:
+"
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn images() -> Result<()> {
+        let filepath = test_data_dir().join("hello.ipynb");
+        let (mut a, d) = simple_fs_adapt_info(&filepath).await?;
+        a.config.adapter_options.notebook_images = true;
+        let files: Vec<_> = NotebookAdapter::new()
+            .adapt(a, &d)
+            .await?
+            .collect::<Result<Vec<_>>>()
+            .await?;
+        let image = &files[1];
+        assert_eq!(image.filepath_hint, filepath.join("cell4-image1.png"));
+        assert_eq!(image.line_prefix, "PREFIX:cell 4 [code]: ");
+        assert_eq!(files.len(), 2);
+        Ok(())
+    }
+}
@@ -0,0 +1,268 @@
+use super::custom::{map_exe_error, pipe_output};
+use super::pdf::adapt_pdf;
+use super::*;
+use crate::adapted_iter::one_file;
+use anyhow::{Result, bail};
+use lazy_static::lazy_static;
+use pdf_extract::{Document, ObjectId};
+use std::io::Write;
+use std::process::Stdio;
+
+static IMAGE_EXTENSIONS: &[&str] = &[
+    "png", "jpg", "jpeg", "tif", "tiff", "bmp", "gif", "webp", "jp2", "pnm", "pbm", "pgm", "ppm",
+];
+static IMAGE_MIME_TYPES: &[&str] = &[
+    "image/png",
+    "image/jpeg",
+    "image/tiff",
+    "image/bmp",
+    "image/gif",
+    "image/webp",
+    "image/jp2",
+    "image/x-portable-anymap",
+];
+const HELP: &str = "Make sure you have tesseract installed.";
+
+lazy_static! {
+    static ref METADATA: AdapterMeta = AdapterMeta {
+        name: "ocr".to_owned(),
+        version: 1,
+        description: "Uses tesseract to recognize the text in images and in pdf pages that have no text layer (e.g. scans).\nThis is slow, so it is disabled by default".to_owned(),
+        recurses: true,
+        fast_matchers: IMAGE_EXTENSIONS
+            .iter()
+            .chain(&["pdf"])
+            .map(|s| FastFileMatcher::FileExtension(s.to_string()))
+            .collect(),
+        slow_matchers: Some(
+            IMAGE_MIME_TYPES
+                .iter()
+                .chain(&["application/pdf"])
+                .map(|s| FileMatcher::MimeType(s.to_string()))
+                .collect()
+        ),
+        keep_fast_matchers_if_accurate: true,
+        disabled_by_default: true
+    };
+}
+
+#[derive(Default, Clone)]
+pub struct OcrAdapter;
+
+impl OcrAdapter {
+    pub fn new() -> Self {
+        Self
+    }
+}
+impl GetMetadata for OcrAdapter {
+    fn metadata(&self) -> &AdapterMeta {
+        &METADATA
+    }
+}
+
+fn tesseract_command() -> std::process::Command {
+    let mut cmd = std::process::Command::new("tesseract");
+    cmd.args(["stdin", "stdout"]);
+    cmd
+}
+
+/// Runs tesseract on an encoded image and returns the recognized text
+fn recognize(image: &[u8]) -> Result<String> {
+    let mut child = tesseract_command()
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .map_err(|e| map_exe_error(e, "tesseract", HELP))?;
+    let mut stdin = child.stdin.take().expect("is piped");
+    let output = std::thread::scope(|s| {
+        // write in a separate thread so tesseract can't block on a full stdout pipe
+        let writer = s.spawn(move || stdin.write_all(image));
+        let output = child.wait_with_output();
+        writer.join().expect("writer panicked")?;
+        output
+    })?;
+    if !output.status.success() {
+        bail!("tesseract failed: {}", output.status);
+    }
+    // tesseract ends each page with a form feed, but this is only a part of the pdf page
+    Ok(String::from_utf8_lossy(&output.stdout).replace('\x0c', ""))
+}
+
+/// Converts raw image samples to a netpbm image, which tesseract can read
+fn to_pnm(width: i64, height: i64, bits_per_component: i64, data: &[u8]) -> Result<Vec<u8>> {
+    let (width, height) = (usize::try_from(width)?, usize::try_from(height)?);
+    let pixels = width.checked_mul(height).context("image is too large")?;
+    let (magic, samples): (&str, Vec<u8>) = match bits_per_component {
+        // rows are padded to whole bytes. in pdf 1 is white, in pbm 1 is black
+        1 if data.len() == width.div_ceil(8) * height => ("P4", data.iter().map(|b| !b).collect()),
+        8 if data.len() == pixels => ("P5", data.to_vec()),
+        8 if Some(data.len()) == pixels.checked_mul(3) => ("P6", data.to_vec()),
+        1 | 8 => bail!(
+            "{} bytes of samples don't match a {width}x{height} image",
+            data.len()
+        ),
+        _ => bail!("unsupported image with {bits_per_component} bits per component"),
+    };
+    let mut pnm = format!("{magic}\n{width} {height}\n").into_bytes();
+    if magic != "P4" {
+        pnm.extend_from_slice(b"255\n");
+    }
+    pnm.extend(samples);
+    Ok(pnm)
+}
+
+/// Recognizes the text of all images on a pdf page
+fn recognize_page(doc: &Document, page_id: ObjectId) -> Result<String> {
+    let mut text = String::new();
+    // pages without images have no xobject dictionary
+    for image in doc.get_page_images(page_id).unwrap_or_default() {
+        let filters = image.filters.unwrap_or_default();
+        let encoded = match filters.iter().map(String::as_str).collect::<Vec<_>>()[..] {
+            ["DCTDecode"] | ["JPXDecode"] => Ok(image.content.to_vec()),
+            _ => {
+                let data = if filters.is_empty() {
+                    Ok(image.content.to_vec())
+                } else {
+                    doc.get_object(image.id)?
+                        .as_stream()?
+                        .decompressed_content()
+                };
+                data.map_err(anyhow::Error::from).and_then(|data| {
+                    to_pnm(
+                        image.width,
+                        image.height,
+                        image.bits_per_component.unwrap_or(8),
+                        &data,
+                    )
+                })
+            }
+        };
+        match encoded {
+            Ok(encoded) => text.push_str(&recognize(&encoded)?),
+            Err(e) => debug!("skipping image {:?}: {e:#}", image.id),
+        }
+    }
+    Ok(text)
+}
+
+#[async_trait]
+impl FileAdapter for OcrAdapter {
+    async fn adapt(
+        &self,
+        ai: AdaptInfo,
+        detection_reason: &FileMatcher,
+    ) -> Result<AdaptedFilesIterBox> {
+        use FastFileMatcher::*;
+        use FileMatcher::*;
+        let is_pdf = match detection_reason {
+            Fast(FileExtension(ext)) => ext == "pdf",
+            MimeType(mime) => mime == "application/pdf",
+        };
+        if is_pdf {
+            return Ok(adapt_pdf(ai, Box::new(recognize_page)));
+        }
+        let AdaptInfo {
+            filepath_hint,
+            inp,
+            line_prefix,
+            archive_recursion_depth,
+            postprocess,
+            config,
+            ..
+        } = ai;
+        let output = pipe_output(
+            &line_prefix,
+            tesseract_command().into(),
+            inp,
+            "tesseract",
+            HELP,
+        )?;
+        Ok(one_file(AdaptInfo {
+            // tesseract separates the pages of multi-page images with form feeds
+            filepath_hint: PathBuf::from(format!(
+                "{}.txt.asciipagebreaks",
+                filepath_hint.to_string_lossy()
+            )),
+            inp: output,
+            line_prefix,
+            is_real_file: false,
+            archive_recursion_depth: archive_recursion_depth + 1,
+            postprocess,
+            config,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use pretty_assertions::assert_eq;
+
+    /// The tests that run tesseract are skipped if it isn't installed
+    fn tesseract_installed() -> bool {
+        let installed = std::process::Command::new("tesseract")
+            .arg("--version")
+            .output()
+            .is_ok();
+        if !installed {
+            eprintln!("tesseract is not installed, skipping");
+        }
+        installed
+    }
+
+    #[tokio::test]
+    async fn image() -> Result<()> {
+        if !tesseract_installed() {
+            return Ok(());
+        }
+        let text = adapt_test_file(&OcrAdapter::new(), "scan.png", false).await?;
+        assert!(
+            text.starts_with("PREFIX:Page 1: Hello from a scan\n"),
+            "{text:?}"
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn pdf_without_text() -> Result<()> {
+        if !tesseract_installed() {
+            return Ok(());
+        }
+        // the page only consists of a flate compressed grayscale image
+        let text = adapt_test_file(&OcrAdapter::new(), "scan.pdf", false).await?;
+        assert!(
+            text.starts_with("PREFIX:Page 1: Hello from a scan\n"),
+            "{text:?}"
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn pdf_with_text() -> Result<()> {
+        // pages with a text layer don't need tesseract
+        assert_eq!(
+            adapt_test_file(&OcrAdapter::new(), "short.pdf", false).await?,
+            "PREFIX:Page 1: hello world
+PREFIX:Page 1: 
+PREFIX:Page 1: this is just a test.
+PREFIX:Page 1:  1
+PREFIX:Page 1: 
+"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn pnm() -> Result<()> {
+        assert_eq!(to_pnm(2, 1, 8, &[0, 255])?, b"P5\n2 1\n255\n\x00\xff");
+        assert_eq!(to_pnm(1, 1, 8, &[1, 2, 3])?, b"P6\n1 1\n255\n\x01\x02\x03");
+        assert_eq!(to_pnm(8, 1, 1, &[0x0f])?, b"P4\n8 1\n\xf0");
+        // rows of 1 bit images are padded to whole bytes
+        assert_eq!(to_pnm(9, 2, 1, &[0; 4])?, b"P4\n9 2\n\xff\xff\xff\xff");
+        assert!(to_pnm(9, 2, 1, &[0; 3]).is_err());
+        assert!(to_pnm(2, 2, 8, &[0]).is_err());
+        assert!(to_pnm(1, 1, 16, &[0, 0]).is_err());
+        Ok(())
+    }
+}
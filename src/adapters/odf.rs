@@ -0,0 +1,296 @@
+use super::blocking::SpooledFile;
+use super::writing::WritingFileAdapter;
+use super::xml::{attribute, column_name, event_text, write_lines};
+use super::*;
+use ::zip::ZipArchive;
+use ::zip::result::ZipError;
+use anyhow::{Result, bail};
+use lazy_static::lazy_static;
+use quick_xml::Reader;
+use quick_xml::events::Event;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use tokio::io::AsyncWrite;
+use tokio_util::io::SyncIoBridge;
+
+static EXTENSIONS: &[&str] = &["odt", "ott", "odp", "otp"];
+static MIME_TYPES: &[&str] = &[
+    "application/vnd.oasis.opendocument.text",
+    "application/vnd.oasis.opendocument.text-template",
+    "application/vnd.oasis.opendocument.presentation",
+    "application/vnd.oasis.opendocument.presentation-template",
+];
+
+lazy_static! {
+    static ref METADATA: AdapterMeta = AdapterMeta {
+        name: "odf".to_owned(),
+        version: 1,
+        description: "Extracts the text of OpenDocument text documents and presentations (odt, odp) including table cell references and slide numbers".to_owned(),
+        recurses: false,
+        fast_matchers: EXTENSIONS
+            .iter()
+            .map(|s| FastFileMatcher::FileExtension(s.to_string()))
+            .collect(),
+        slow_matchers: Some(
+            MIME_TYPES
+                .iter()
+                .map(|s| FileMatcher::MimeType(s.to_string()))
+                .collect()
+        ),
+        keep_fast_matchers_if_accurate: true,
+        disabled_by_default: false
+    };
+}
+#[derive(Default, Clone)]
+pub struct OdfAdapter;
+
+impl OdfAdapter {
+    pub fn new() -> Self {
+        Self
+    }
+}
+impl GetMetadata for OdfAdapter {
+    fn metadata(&self) -> &AdapterMeta {
+        &METADATA
+    }
+}
+
+/// The position within a table
+struct Table {
+    name: String,
+    /// one based, 0 before the first row
+    row: u32,
+    column: u32,
+    rows_repeated: u32,
+}
+
+/// Text that is read into a prefix instead of the surrounding paragraph
+enum Capture {
+    NoteCitation,
+    Creator,
+}
+
+fn parse_repeat(value: Option<String>) -> u32 {
+    value.and_then(|v| v.parse().ok()).unwrap_or(1)
+}
+
+/// Writes each paragraph of the body of `content.xml` as a line.
+///
+/// Lines are prefixed with where they are in the document, e.g. `Table1!B2: ` for table cells,
+/// `slide 2: ` for presentations, `footnote 1: ` for notes and `comment (author): ` for annotations.
+fn write_content(reader: &mut Reader<impl BufRead>, out: &mut impl Write) -> Result<()> {
+    let mut prefixes: Vec<String> = vec![String::new()];
+    // paragraphs can be nested, e.g. in notes and annotations
+    let mut paragraphs: Vec<(String, String)> = Vec::new();
+    let mut tables: Vec<Table> = Vec::new();
+    let mut slide = 0;
+    let mut note_citation = String::new();
+    let mut capture: Option<(Capture, String)> = None;
+    let mut buf = Vec::new();
+    let mut skip_buf = Vec::new();
+    loop {
+        let event = reader.read_event_into(&mut buf)?;
+        let prefix = prefixes.last().cloned().unwrap_or_default();
+        match &event {
+            Event::Start(e) => match e.local_name().as_ref() {
+                b"p" | b"h" => paragraphs.push((prefix, String::new())),
+                b"page" => {
+                    slide += 1;
+                    prefixes.push(format!("slide {slide}: "));
+                }
+                b"notes" => prefixes.push(format!("slide {slide} notes: ")),
+                b"table" => tables.push(Table {
+                    name: attribute(e, b"name")?.unwrap_or_default(),
+                    row: 0,
+                    column: 0,
+                    rows_repeated: 1,
+                }),
+                b"table-row" => {
+                    if let Some(table) = tables.last_mut() {
+                        table.row += table.rows_repeated;
+                        table.rows_repeated = parse_repeat(attribute(e, b"number-rows-repeated")?);
+                        table.column = 0;
+                    }
+                }
+                // repeated cells with content are only output once, at their first position
+                b"table-cell" | b"covered-table-cell" => {
+                    let repeat = parse_repeat(attribute(e, b"number-columns-repeated")?);
+                    match tables.last_mut() {
+                        Some(table) => {
+                            prefixes.push(format!(
+                                "{}!{}{}: ",
+                                table.name,
+                                column_name(table.column),
+                                table.row.max(1)
+                            ));
+                            table.column = table.column.saturating_add(repeat);
+                        }
+                        None => prefixes.push(prefix),
+                    }
+                }
+                b"note" => {
+                    note_citation = match attribute(e, b"note-class")?.as_deref() {
+                        Some("endnote") => "endnote".to_owned(),
+                        _ => "footnote".to_owned(),
+                    }
+                }
+                b"note-citation" => capture = Some((Capture::NoteCitation, String::new())),
+                b"note-body" => prefixes.push(format!("{note_citation}: ")),
+                b"annotation" => prefixes.push("comment: ".to_owned()),
+                _ if e.name().as_ref() == b"dc:creator" => {
+                    capture = Some((Capture::Creator, String::new()))
+                }
+                b"tab" => {
+                    if let Some((_, text)) = paragraphs.last_mut() {
+                        text.push('\t');
+                    }
+                }
+                // deleted text of tracked changes and the date of annotations
+                _ if matches!(e.name().as_ref(), b"text:tracked-changes" | b"dc:date") => {
+                    reader.read_to_end_into(e.name(), &mut skip_buf)?;
+                }
+                _ => {}
+            },
+            Event::Empty(e) => {
+                let text = paragraphs.last_mut().map(|(_, text)| text);
+                match (e.local_name().as_ref(), text) {
+                    (b"s", Some(text)) => {
+                        let count = parse_repeat(attribute(e, b"c")?) as usize;
+                        text.push_str(&" ".repeat(count.min(1024)));
+                    }
+                    (b"tab", Some(text)) => text.push('\t'),
+                    (b"line-break", Some(text)) => text.push('\n'),
+                    (b"table-row", _) => {
+                        if let Some(table) = tables.last_mut() {
+                            table.row += table.rows_repeated;
+                            table.rows_repeated =
+                                parse_repeat(attribute(e, b"number-rows-repeated")?);
+                        }
+                    }
+                    (b"table-cell" | b"covered-table-cell", _) => {
+                        if let Some(table) = tables.last_mut() {
+                            let repeat = parse_repeat(attribute(e, b"number-columns-repeated")?);
+                            table.column = table.column.saturating_add(repeat);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            Event::End(e) => match e.local_name().as_ref() {
+                b"p" | b"h" => {
+                    if let Some((prefix, text)) = paragraphs.pop() {
+                        write_lines(out, &prefix, &text)?;
+                    }
+                }
+                b"table" => {
+                    tables.pop();
+                }
+                b"page"
+                | b"notes"
+                | b"table-cell"
+                | b"covered-table-cell"
+                | b"note-body"
+                | b"annotation" => {
+                    prefixes.pop();
+                }
+                b"note-citation" | b"creator" => match capture.take() {
+                    Some((Capture::NoteCitation, citation)) => {
+                        note_citation = format!("{note_citation} {}", citation.trim());
+                    }
+                    Some((Capture::Creator, creator)) => {
+                        if let Some(prefix) = prefixes.last_mut() {
+                            *prefix = format!("comment ({}): ", creator.trim());
+                        }
+                    }
+                    None => {}
+                },
+                _ => {}
+            },
+            Event::Eof => break,
+            event => {
+                if let Some(text) = event_text(event)? {
+                    if let Some((_, captured)) = capture.as_mut() {
+                        captured.push_str(&text);
+                    } else if let Some((_, paragraph)) = paragraphs.last_mut() {
+                        paragraph.push_str(&text);
+                    }
+                }
+            }
+        }
+        buf.clear();
+    }
+    Ok(())
+}
+
+fn synchronous_dump_odf(ai: AdaptInfo, out: impl Write) -> Result<()> {
+    // zip files have their index at the end
+    let file = SpooledFile::new(&ai.filepath_hint, ai.is_real_file, ai.inp)?;
+    let mut package = ZipArchive::new(file.open()?)
+        .with_context(|| format!("opening {}", ai.filepath_hint.display()))?;
+    let content = match package.by_name("content.xml") {
+        Ok(content) => content,
+        Err(ZipError::FileNotFound) => bail!("no content.xml, not an opendocument file"),
+        Err(e) => return Err(e).context("reading content.xml"),
+    };
+    let mut reader = Reader::from_reader(BufReader::new(content));
+    let mut out = BufWriter::new(out);
+    write_content(&mut reader, &mut out).context("reading content.xml")?;
+    out.flush()?;
+    Ok(())
+}
+
+#[async_trait]
+impl WritingFileAdapter for OdfAdapter {
+    async fn adapt_write(
+        ai: AdaptInfo,
+        _detection_reason: &FileMatcher,
+        oup: Pin<Box<dyn AsyncWrite + Send>>,
+    ) -> Result<()> {
+        let oup_sync = SyncIoBridge::new(oup);
+        tokio::task::spawn_blocking(|| synchronous_dump_odf(ai, oup_sync))
+            .await?
+            .context("in synchronous odf task")?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use pretty_assertions::assert_eq;
+
+    #[tokio::test]
+    async fn odt() -> Result<()> {
+        // notes are written before the paragraph they are in
+        assert_eq!(
+            adapt_test_file(&OdfAdapter::new(), "hello.odt", true).await?,
+            "PREFIX:A heading
+PREFIX:footnote 1: A footnote
+PREFIX:Hello\tworld &   everyone.
+PREFIX:first line
+PREFIX:second line
+PREFIX:comment (Jane Doe): A comment
+PREFIX:commented text
+PREFIX:a list item
+PREFIX:Table1!A1: cell one
+PREFIX:Table1!B1: cell two
+PREFIX:
+"
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn odp() -> Result<()> {
+        assert_eq!(
+            adapt_test_file(&OdfAdapter::new(), "hello.odp", true).await?,
+            "PREFIX:slide 1: Title slide
+PREFIX:slide 2: Second slide
+PREFIX:slide 2: with two spans
+PREFIX:slide 2 notes: Speaker notes
+PREFIX:
+"
+        );
+        Ok(())
+    }
+}
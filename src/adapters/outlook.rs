@@ -0,0 +1,527 @@
+use super::blocking::{EntrySender, SpooledFile, blocking_entries};
+use super::*;
+use anyhow::Result;
+use lazy_static::lazy_static;
+use mime2ext::mime2ext;
+use outlook_pst::{
+    AnsiPstFile, UnicodePstFile,
+    ltp::prop_context::PropertyValue,
+    messaging::{
+        attachment::{AnsiAttachment, Attachment, AttachmentData, UnicodeAttachment},
+        folder::Folder,
+        message::{AnsiMessage, Message, UnicodeMessage},
+        store::{AnsiStore, EntryId, Store, UnicodeStore},
+    },
+    ndb::node_id::NodeId,
+};
+use std::io::{Read, Seek};
+use std::path::Path;
+use std::rc::Rc;
+
+static EXTENSIONS: &[&str] = &["msg", "pst", "ost"];
+static MIME_TYPES: &[&str] = &[
+    "application/vnd.ms-outlook",
+    "application/vnd.ms-outlook-pst",
+];
+
+lazy_static! {
+    static ref METADATA: AdapterMeta = AdapterMeta {
+        name: "outlook".to_owned(),
+        version: 1,
+        description:
+            "Reads Outlook messages (.msg) and mailboxes (.pst, .ost) and runs extractors on the message bodies and attachments"
+                .to_owned(),
+        recurses: true,
+        fast_matchers: EXTENSIONS
+            .iter()
+            .map(|s| FastFileMatcher::FileExtension(s.to_string()))
+            .collect(),
+        slow_matchers: Some(
+            MIME_TYPES
+                .iter()
+                .map(|s| FileMatcher::MimeType(s.to_string()))
+                .collect()
+        ),
+        keep_fast_matchers_if_accurate: true,
+        disabled_by_default: false
+    };
+}
+
+#[derive(Default, Clone)]
+pub struct OutlookAdapter;
+
+impl OutlookAdapter {
+    pub fn new() -> Self {
+        Self
+    }
+}
+impl GetMetadata for OutlookAdapter {
+    fn metadata(&self) -> &AdapterMeta {
+        &METADATA
+    }
+}
+
+// MAPI property ids, see [MS-OXPROPS]
+const PR_SUBJECT: u16 = 0x0037;
+const PR_BODY: u16 = 0x1000;
+const PR_HTML: u16 = 0x1013;
+const PR_DISPLAY_NAME: u16 = 0x3001;
+const PR_ATTACH_DATA: u16 = 0x3701;
+const PR_ATTACH_FILENAME: u16 = 0x3704;
+const PR_ATTACH_LONG_FILENAME: u16 = 0x3707;
+const PR_ATTACH_MIME_TAG: u16 = 0x370E;
+const PR_INTERNET_CPID: u16 = 0x3FDE;
+const PR_MESSAGE_CODEPAGE: u16 = 0x3FFD;
+
+/// The parts of a message that are yielded as files
+#[derive(Default)]
+struct Mail {
+    subject: Option<String>,
+    body: Option<String>,
+    /// in the charset declared by the html itself, so it is passed on as is
+    html: Option<Vec<u8>>,
+    attachments: Vec<MailAttachment>,
+}
+
+#[derive(Default)]
+struct MailAttachment {
+    name: Option<String>,
+    mime: Option<String>,
+    content: AttachmentContent,
+}
+
+#[derive(Default)]
+enum AttachmentContent {
+    /// e.g. attachments that only link to a file
+    #[default]
+    None,
+    Data(Vec<u8>),
+    Message(Mail),
+    /// yielded as a `[rga: ...]` marker, e.g. messages embedded in a pst file
+    Unsupported(&'static str),
+}
+
+fn decode_string8(data: &[u8], codepage: Option<u16>) -> String {
+    let encoding = codepage
+        .and_then(codepage::to_encoding)
+        .unwrap_or(encoding_rs::WINDOWS_1252);
+    encoding
+        .decode_without_bom_handling(data)
+        .0
+        .trim_end_matches('\0')
+        .to_owned()
+}
+
+/// Replaces characters that would change the meaning of an entry path
+fn sanitize(name: &str) -> String {
+    name.trim().replace(['/', '\\', '\0'], "_")
+}
+
+/// Yields the bodies and attachments of a message as files in `dir`
+fn send_mail(entries: &EntrySender, dir: &Path, mail: Mail) -> Result<()> {
+    if let Some(body) = mail.body {
+        entries.send(dir.join("body.txt"), body.as_bytes())?;
+    }
+    if let Some(html) = mail.html {
+        entries.send(dir.join("body.html"), html.as_slice())?;
+    }
+    for (i, attachment) in mail.attachments.into_iter().enumerate() {
+        let name = match (&attachment.name, &attachment.mime) {
+            (Some(name), _) if !name.trim().is_empty() => sanitize(name),
+            (_, Some(mime)) if mime2ext(mime).is_some() => {
+                format!("attachment{}.{}", i + 1, mime2ext(mime).unwrap())
+            }
+            _ => format!("attachment{}", i + 1),
+        };
+        match attachment.content {
+            AttachmentContent::None => {}
+            AttachmentContent::Data(data) => entries.send(dir.join(name), data.as_slice())?,
+            AttachmentContent::Message(message) => send_mail(entries, &dir.join(name), message)?,
+            AttachmentContent::Unsupported(message) => {
+                entries.send_message(&dir.join(name), message)?
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Reads the properties of a .msg file (a compound file binary), see [MS-OXMSG]
+struct MsgReader<F> {
+    cfb: cfb::CompoundFile<F>,
+}
+
+impl<F: Read + Seek> MsgReader<F> {
+    fn stream(&mut self, dir: &Path, name: &str) -> Option<Vec<u8>> {
+        let mut data = Vec::new();
+        self.cfb
+            .open_stream(dir.join(name))
+            .ok()?
+            .read_to_end(&mut data)
+            .ok()?;
+        Some(data)
+    }
+
+    fn binary(&mut self, dir: &Path, id: u16) -> Option<Vec<u8>> {
+        self.stream(dir, &format!("__substg1.0_{id:04X}0102"))
+    }
+
+    fn string(&mut self, dir: &Path, id: u16, codepage: Option<u16>) -> Option<String> {
+        if let Some(data) = self.stream(dir, &format!("__substg1.0_{id:04X}001F")) {
+            let utf16: Vec<u16> = data
+                .chunks_exact(2)
+                .map(|c| u16::from_le_bytes([c[0], c[1]]))
+                .collect();
+            return Some(
+                String::from_utf16_lossy(&utf16)
+                    .trim_end_matches('\0')
+                    .to_owned(),
+            );
+        }
+        let data = self.stream(dir, &format!("__substg1.0_{id:04X}001E"))?;
+        Some(decode_string8(&data, codepage))
+    }
+
+    /// Reads a 32-bit integer from the fixed-size properties of a storage.
+    ///
+    /// The header before the property entries is 32 bytes long for the top-level message,
+    /// 24 bytes for embedded messages and 8 bytes for attachments.
+    fn integer(&mut self, dir: &Path, header_len: usize, id: u16) -> Option<u32> {
+        let data = self.stream(dir, "__properties_version1.0")?;
+        data.get(header_len..)?.chunks_exact(16).find_map(|entry| {
+            let tag = u32::from_le_bytes(entry[0..4].try_into().unwrap());
+            (tag == (u32::from(id) << 16 | 0x0003))
+                .then(|| u32::from_le_bytes(entry[8..12].try_into().unwrap()))
+        })
+    }
+
+    fn mail(&mut self, dir: &Path, header_len: usize) -> Result<Mail> {
+        let codepage = self
+            .integer(dir, header_len, PR_MESSAGE_CODEPAGE)
+            .and_then(|c| u16::try_from(c).ok());
+        let mut attachment_dirs = Vec::new();
+        for entry in self.cfb.read_storage(dir)? {
+            if entry.is_storage() && entry.name().starts_with("__attach_version1.0_#") {
+                attachment_dirs.push(entry.path().to_owned());
+            }
+        }
+        let mut attachments = Vec::new();
+        for dir in attachment_dirs {
+            let embedded = dir.join("__substg1.0_3701000D");
+            let content = if self.cfb.is_storage(&embedded) {
+                AttachmentContent::Message(self.mail(&embedded, 24)?)
+            } else if let Some(data) = self.binary(&dir, PR_ATTACH_DATA) {
+                AttachmentContent::Data(data)
+            } else {
+                AttachmentContent::None
+            };
+            attachments.push(MailAttachment {
+                name: self
+                    .string(&dir, PR_ATTACH_LONG_FILENAME, codepage)
+                    .or_else(|| self.string(&dir, PR_ATTACH_FILENAME, codepage))
+                    .or_else(|| self.string(&dir, PR_DISPLAY_NAME, codepage)),
+                mime: self.string(&dir, PR_ATTACH_MIME_TAG, codepage),
+                content,
+            });
+        }
+        Ok(Mail {
+            subject: self.string(dir, PR_SUBJECT, codepage),
+            body: self.string(dir, PR_BODY, codepage),
+            html: self.binary(dir, PR_HTML),
+            attachments,
+        })
+    }
+}
+
+fn pst_string(value: Option<&PropertyValue>, codepage: Option<u16>) -> Option<String> {
+    match value? {
+        PropertyValue::String8(value) => Some(decode_string8(value.buffer(), codepage)),
+        PropertyValue::Unicode(value) => Some(String::from_utf16_lossy(value.buffer())),
+        _ => None,
+    }
+}
+
+/// Builds a [`Mail`] from a message and its attachments, which have to be read with the types
+/// of the concrete pst format
+fn pst_mail(message: &dyn Message, attachments: &[Rc<dyn Attachment>]) -> Mail {
+    let properties = message.properties();
+    let codepage = [PR_INTERNET_CPID, PR_MESSAGE_CODEPAGE]
+        .iter()
+        .find_map(|&id| match properties.get(id) {
+            Some(PropertyValue::Integer32(codepage)) => u16::try_from(*codepage).ok(),
+            _ => None,
+        });
+    // subjects can start with a marker and the length of a prefix like "RE: "
+    let subject = pst_string(properties.get(PR_SUBJECT), codepage).map(|subject| {
+        match subject.strip_prefix('\u{1}') {
+            Some(subject) => subject.chars().skip(1).collect(),
+            None => subject,
+        }
+    });
+    let html = match properties.get(PR_HTML) {
+        Some(PropertyValue::Binary(html)) => Some(html.buffer().to_vec()),
+        html => pst_string(html, codepage).map(String::into_bytes),
+    };
+    let attachments = attachments
+        .iter()
+        .map(|attachment| {
+            let properties = attachment.properties();
+            let string = |id| pst_string(properties.get(id), codepage);
+            MailAttachment {
+                name: string(PR_ATTACH_LONG_FILENAME)
+                    .or_else(|| string(PR_ATTACH_FILENAME))
+                    .or_else(|| string(PR_DISPLAY_NAME)),
+                mime: string(PR_ATTACH_MIME_TAG),
+                content: match attachment.data() {
+                    Some(AttachmentData::Binary(data)) => {
+                        AttachmentContent::Data(data.buffer().to_vec())
+                    }
+                    // the library only exposes embedded messages as `dyn Message`, so
+                    // their attachments can't be read
+                    Some(AttachmentData::Message(_)) => {
+                        AttachmentContent::Unsupported("embedded message not supported")
+                    }
+                    None => AttachmentContent::None,
+                },
+            }
+        })
+        .collect();
+    Mail {
+        subject,
+        body: pst_string(properties.get(PR_BODY), codepage),
+        html,
+        attachments,
+    }
+}
+
+/// Reads the attachments of a message, skipping the ones the library can't read
+fn read_attachments(
+    message: &dyn Message,
+    read: impl Fn(NodeId) -> std::io::Result<Rc<dyn Attachment>>,
+) -> Vec<Rc<dyn Attachment>> {
+    let Some(table) = message.attachment_table() else {
+        return Vec::new();
+    };
+    table
+        .rows_matrix()
+        .enumerate()
+        .filter_map(|(i, row)| match read(NodeId::from(u32::from(row.id()))) {
+            Ok(attachment) => Some(attachment),
+            Err(e) => {
+                warn!("could not read attachment {}: {e}", i + 1);
+                None
+            }
+        })
+        .collect()
+}
+
+type OpenMessage<'a> = dyn Fn(&EntryId) -> std::io::Result<Mail> + 'a;
+
+/// Yields the messages of a folder and its sub folders
+fn send_folder(
+    entries: &EntrySender,
+    store: &dyn Store,
+    folder: &dyn Folder,
+    dir: &Path,
+    open_message: &OpenMessage,
+) -> Result<()> {
+    if let Some(contents) = folder.contents_table() {
+        for (i, row) in contents.rows_matrix().enumerate() {
+            let entry_id = store
+                .properties()
+                .make_entry_id(NodeId::from(u32::from(row.id())))?;
+            let mail = match open_message(&entry_id) {
+                Ok(mail) => mail,
+                Err(e) => {
+                    warn!("could not read message {} in {}: {e}", i + 1, dir.display());
+                    continue;
+                }
+            };
+            let name = match &mail.subject {
+                Some(subject) if !subject.trim().is_empty() => sanitize(subject),
+                _ => format!("message{}", i + 1),
+            };
+            send_mail(entries, &dir.join(name), mail)?;
+        }
+    }
+    if let Some(hierarchy) = folder.hierarchy_table() {
+        for row in hierarchy.rows_matrix() {
+            let entry_id = store
+                .properties()
+                .make_entry_id(NodeId::from(u32::from(row.id())))?;
+            let sub_folder = store.open_folder(&entry_id)?;
+            let name = sanitize(&sub_folder.properties().display_name()?);
+            send_folder(
+                entries,
+                store,
+                sub_folder.as_ref(),
+                &dir.join(name),
+                open_message,
+            )?;
+        }
+    }
+    Ok(())
+}
+
+fn send_store(entries: &EntrySender, store: &dyn Store, open_message: &OpenMessage) -> Result<()> {
+    // the folders that are visible to the user, without the search folders and other internals
+    let ipm_sub_tree = store.open_folder(&store.properties().ipm_sub_tree_entry_id()?)?;
+    send_folder(
+        entries,
+        store,
+        ipm_sub_tree.as_ref(),
+        Path::new(""),
+        open_message,
+    )
+}
+
+/// Reads a message of a unicode pst file and its attachments
+fn unicode_mail(message: Rc<UnicodeMessage>) -> Mail {
+    let attachments = read_attachments(message.as_ref(), |node| {
+        UnicodeAttachment::read(message.clone(), node, None).map(|a| a as Rc<dyn Attachment>)
+    });
+    pst_mail(message.as_ref(), &attachments)
+}
+
+/// Reads a message of an ansi pst file and its attachments
+fn ansi_mail(message: Rc<AnsiMessage>) -> Mail {
+    let attachments = read_attachments(message.as_ref(), |node| {
+        AnsiAttachment::read(message.clone(), node, None).map(|a| a as Rc<dyn Attachment>)
+    });
+    pst_mail(message.as_ref(), &attachments)
+}
+
+/// The `wVer` values of the header of pst files before Outlook 2003, which use 32-bit offsets
+/// and 8-bit strings, see [MS-PST] 2.2.2.6
+const PST_ANSI_VERSIONS: [u16; 2] = [14, 15];
+
+fn read_pst(path: &Path, version: u16, entries: &EntrySender) -> Result<()> {
+    if PST_ANSI_VERSIONS.contains(&version) {
+        let store = AnsiStore::read(Rc::new(AnsiPstFile::open(path)?))?;
+        send_store(entries, store.as_ref(), &|entry_id| {
+            Ok(ansi_mail(AnsiMessage::read(store.clone(), entry_id, None)?))
+        })
+    } else {
+        let store = UnicodeStore::read(Rc::new(UnicodePstFile::open(path)?))?;
+        send_store(entries, store.as_ref(), &|entry_id| {
+            Ok(unicode_mail(UnicodeMessage::read(
+                store.clone(),
+                entry_id,
+                None,
+            )?))
+        })
+    }
+}
+
+fn read_entries(ai: AdaptInfo, entries: &EntrySender) -> Result<()> {
+    let file = SpooledFile::new(&ai.filepath_hint, ai.is_real_file, ai.inp)?;
+    // magic, crc, client magic and version of a pst header
+    let mut header = [0u8; 12];
+    file.open()?.read_exact(&mut header)?;
+    if header.starts_with(b"!BDN") {
+        let version = u16::from_le_bytes([header[10], header[11]]);
+        return read_pst(file.path(), version, entries)
+            .with_context(|| format!("reading pst file {}", ai.filepath_hint.display()));
+    }
+    let mut msg = MsgReader {
+        cfb: cfb::CompoundFile::open(file.open()?)
+            .with_context(|| format!("opening msg file {}", ai.filepath_hint.display()))?,
+    };
+    let mail = msg.mail(Path::new("/"), 32)?;
+    send_mail(entries, Path::new(""), mail)
+}
+
+#[async_trait]
+impl FileAdapter for OutlookAdapter {
+    async fn adapt(
+        &self,
+        ai: AdaptInfo,
+        _detection_reason: &FileMatcher,
+    ) -> Result<AdaptedFilesIterBox> {
+        Ok(blocking_entries(ai, read_entries))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use pretty_assertions::assert_eq;
+    use tokio::io::AsyncReadExt;
+    use tokio_stream::StreamExt;
+
+    #[tokio::test]
+    async fn msg() -> Result<()> {
+        let filepath = test_data_dir().join("hello.msg");
+        let (a, d) = simple_fs_adapt_info(&filepath).await?;
+        let mut r = OutlookAdapter::new().adapt(a, &d).await?;
+        let mut files = Vec::new();
+        while let Some(file) = r.next().await {
+            let mut file = file?;
+            let mut buf = String::new();
+            file.inp.read_to_string(&mut buf).await?;
+            files.push((file.line_prefix, buf));
+        }
+        assert_eq!(
+            files,
+            [
+                (
+                    "PREFIX:body.txt: ",
+                    "Hi all,\r\nthe report is attached.\r\n"
+                ),
+                (
+                    "PREFIX:body.html: ",
+                    "<html><body><p>Hi all,</p><p>the report is attached.</p></body></html>"
+                ),
+                ("PREFIX:report.txt: ", "revenue: 42\n"),
+                // embedded messages are yielded as directories
+                ("PREFIX:Forwarded note/body.txt: ", "café at noon\r\n"),
+            ]
+            .map(|(prefix, content)| (prefix.to_owned(), content.to_owned()))
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn pst() -> Result<()> {
+        // the message also has a forwarded message as attachment, but the pst library can't
+        // read embedded messages (PtypObject properties) yet, so only that attachment is skipped
+        assert_eq!(
+            adapt_test_file(&OutlookAdapter::new(), "hello.pst", true).await?,
+            "PREFIX:Inbox/Quarterly report/body.txt: Hi all,\r
+PREFIX:Inbox/Quarterly report/body.txt: the report is attached.\r
+PREFIX:Inbox/Quarterly report/body.txt: 
+PREFIX:Inbox/Quarterly report/report.txt: revenue: 42
+PREFIX:Inbox/Quarterly report/report.txt: 
+"
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn corrupt_pst() -> Result<()> {
+        let mut data = std::fs::read(test_data_dir().join("hello.pst"))?;
+        // the header is intact, but the pages it points to are missing
+        data.truncate(0x4600);
+        let (a, d) =
+            simple_adapt_info(Path::new("hello.pst"), Box::pin(std::io::Cursor::new(data)));
+        let err = match adapted_to_vec(OutlookAdapter::new().adapt(a, &d).await?).await {
+            Ok(_) => panic!("corrupt pst file was read"),
+            Err(e) => format!("{e:#}"),
+        };
+        // the error of the unicode reader, not of an ansi reader that was tried afterwards
+        assert_eq!(
+            err,
+            "reading pst file hello.pst: failed to fill whole buffer"
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn empty_pst() -> Result<()> {
+        assert_eq!(
+            adapt_test_file(&OutlookAdapter::new(), "empty.pst", true).await?,
+            ""
+        );
+        Ok(())
+    }
+}
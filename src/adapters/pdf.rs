@@ -0,0 +1,166 @@
+use super::*;
+use crate::adapted_iter::one_file;
+use crate::{join_handle_to_stream, to_io_err};
+use anyhow::{Result, bail};
+use lazy_static::lazy_static;
+use log::warn;
+use pdf_extract::{Document, ObjectId};
+use std::io::Write;
+use tokio::io::{AsyncReadExt, AsyncWrite};
+use tokio_util::io::SyncIoBridge;
+
+static EXTENSIONS: &[&str] = &["pdf"];
+
+lazy_static! {
+    /// Whether the poppler adapter can run. The native extractor is only enabled by default if it can't.
+    static ref PDFTOTEXT_FOUND: bool = which::which("pdftotext").is_ok();
+    static ref METADATA: AdapterMeta = AdapterMeta {
+        name: "pdf".to_owned(),
+        version: 1,
+        description: "Extracts plain text from PDF files without external programs.\nUsed instead of poppler if pdftotext is not installed".to_owned(),
+        recurses: true,
+        fast_matchers: EXTENSIONS
+            .iter()
+            .map(|s| FastFileMatcher::FileExtension(s.to_string()))
+            .collect(),
+        slow_matchers: Some(vec![FileMatcher::MimeType("application/pdf".to_owned())]),
+        keep_fast_matchers_if_accurate: true,
+        disabled_by_default: true
+    };
+}
+
+/// Whether `pdftotext` is in the PATH
+pub fn pdftotext_found() -> bool {
+    *PDFTOTEXT_FOUND
+}
+
+#[derive(Default, Clone)]
+pub struct PdfAdapter;
+
+impl PdfAdapter {
+    pub fn new() -> Self {
+        Self
+    }
+}
+impl GetMetadata for PdfAdapter {
+    fn metadata(&self) -> &AdapterMeta {
+        &METADATA
+    }
+}
+
+/// Returns the text of a page that has no text layer, e.g. by running OCR on its images
+pub type EmptyPageFn = dyn FnMut(&Document, ObjectId) -> Result<String> + Send;
+
+/// Writes the text of each page followed by an ASCII form feed, like `pdftotext`
+fn write_pages(data: &[u8], out: &mut impl Write, empty_page: &mut EmptyPageFn) -> Result<()> {
+    let doc = Document::load_mem(data)?;
+    if doc.is_encrypted() {
+        bail!("encrypted pdf files are not supported");
+    }
+    for (page_num, page_id) in doc.get_pages() {
+        let mut text = String::new();
+        let res = pdf_extract::output_doc_page(
+            &doc,
+            &mut pdf_extract::PlainTextOutput::new(&mut text),
+            page_num,
+        );
+        // keep the page numbers of the following pages correct
+        if let Err(e) = res {
+            warn!("could not extract text of page {page_num}: {e}");
+        }
+        if text.trim().is_empty() {
+            text = empty_page(&doc, page_id)
+                .with_context(|| format!("page {page_num} without text"))?;
+        }
+        let text = text.trim_matches('\n');
+        if !text.is_empty() {
+            writeln!(out, "{text}")?;
+        }
+        out.write_all(b"\x0c")?;
+    }
+    Ok(())
+}
+
+fn synchronous_dump_pdf(
+    ai: AdaptInfo,
+    oup: impl AsyncWrite + Unpin,
+    empty_page: &mut EmptyPageFn,
+) -> Result<()> {
+    // the cross reference table is at the end of the file
+    let mut data = Vec::new();
+    std::io::Read::read_to_end(&mut SyncIoBridge::new(ai.inp), &mut data)?;
+    let mut out = std::io::BufWriter::new(SyncIoBridge::new(oup));
+    write_pages(&data, &mut out, empty_page)
+        .with_context(|| format!("reading {}", ai.filepath_hint.display()))?;
+    out.flush()?;
+    Ok(())
+}
+
+/// Extracts the text of a pdf file in a separate thread.
+///
+/// The output has ASCII page breaks so it is passed on to the `postprocpagebreaks` adapter.
+pub fn adapt_pdf(ai: AdaptInfo, mut empty_page: Box<EmptyPageFn>) -> AdaptedFilesIterBox {
+    let (w, r) = tokio::io::duplex(128 * 1024);
+    let filepath_hint = format!("{}.txt.asciipagebreaks", ai.filepath_hint.to_string_lossy());
+    let line_prefix = ai.line_prefix.clone();
+    let archive_recursion_depth = ai.archive_recursion_depth + 1;
+    let postprocess = ai.postprocess;
+    let config = ai.config.clone();
+    let joiner = tokio::task::spawn_blocking(move || {
+        synchronous_dump_pdf(ai, w, &mut empty_page)
+            .context("in synchronous pdf task")
+            .map_err(to_io_err)
+    });
+    one_file(AdaptInfo {
+        filepath_hint: filepath_hint.into(),
+        inp: Box::pin(r.chain(join_handle_to_stream(joiner))),
+        line_prefix,
+        is_real_file: false,
+        archive_recursion_depth,
+        postprocess,
+        config,
+    })
+}
+
+#[async_trait]
+impl FileAdapter for PdfAdapter {
+    async fn adapt(
+        &self,
+        ai: AdaptInfo,
+        _detection_reason: &FileMatcher,
+    ) -> Result<AdaptedFilesIterBox> {
+        Ok(adapt_pdf(ai, Box::new(|_, _| Ok(String::new()))))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use pretty_assertions::assert_eq;
+
+    #[tokio::test]
+    async fn short() -> Result<()> {
+        assert_eq!(
+            adapt_test_file(&PdfAdapter::new(), "short.pdf", false).await?,
+            "PREFIX:Page 1: hello world
+PREFIX:Page 1: 
+PREFIX:Page 1: this is just a test.
+PREFIX:Page 1:  1
+PREFIX:Page 1: 
+"
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn twoblankpages() -> Result<()> {
+        // empty pages still count towards the page numbers
+        let text = adapt_test_file(&PdfAdapter::new(), "twoblankpages.pdf", false).await?;
+        assert!(
+            text.starts_with("PREFIX:Page 1: \nPREFIX:Page 2: \nPREFIX:Page 3: "),
+            "{text:?}"
+        );
+        Ok(())
+    }
+}
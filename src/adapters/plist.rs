@@ -0,0 +1,223 @@
+use super::xml::write_lines;
+use super::*;
+use ::plist::Value;
+use anyhow::Result;
+use async_stream::stream;
+use lazy_static::lazy_static;
+use std::io::{Cursor, Write};
+use tokio::io::AsyncReadExt;
+
+static EXTENSIONS: &[&str] = &["plist", "bplist"];
+static MIME_TYPES: &[&str] = &["application/x-plist", "application/x-bplist"];
+/// Data blobs starting with these bytes are passed on to the other adapters, with the given extension
+static ARCHIVE_MAGIC: &[(&[u8], &str)] = &[
+    (b"bplist00", "plist"),
+    (b"PK\x03\x04", "zip"),
+    (b"\x1f\x8b", "gz"),
+    (b"BZh", "bz2"),
+    (b"\xfd7zXZ\x00", "xz"),
+    (b"\x28\xb5\x2f\xfd", "zst"),
+    (b"7z\xbc\xaf\x27\x1c", "7z"),
+    (b"Rar!\x1a\x07", "rar"),
+];
+
+lazy_static! {
+    static ref METADATA: AdapterMeta = AdapterMeta {
+        name: "plist".to_owned(),
+        version: 1,
+        description: "Flattens binary, XML and OpenStep property lists to `key.path: value` lines.\nData values that contain archives (e.g. nested binary plists) are passed on to the other adapters".to_owned(),
+        recurses: true,
+        fast_matchers: EXTENSIONS
+            .iter()
+            .map(|s| FastFileMatcher::FileExtension(s.to_string()))
+            .collect(),
+        slow_matchers: Some(
+            MIME_TYPES
+                .iter()
+                .map(|s| FileMatcher::MimeType(s.to_string()))
+                .collect()
+        ),
+        keep_fast_matchers_if_accurate: true,
+        disabled_by_default: false
+    };
+}
+
+#[derive(Default, Clone)]
+pub struct PlistAdapter;
+
+impl PlistAdapter {
+    pub fn new() -> Self {
+        Self
+    }
+}
+impl GetMetadata for PlistAdapter {
+    fn metadata(&self) -> &AdapterMeta {
+        &METADATA
+    }
+}
+
+/// A data value that looks like an archive
+struct Archive {
+    path: String,
+    extension: &'static str,
+    data: Vec<u8>,
+}
+
+/// Writes every leaf of a plist value as `path: value` and collects the archives in its data values
+fn flatten(value: Value, path: &str, out: &mut Vec<u8>, archives: &mut Vec<Archive>) -> Result<()> {
+    let prefix = if path.is_empty() {
+        String::new()
+    } else {
+        format!("{path}: ")
+    };
+    match value {
+        Value::Dictionary(dict) if dict.is_empty() => writeln!(out, "{prefix}{{}}")?,
+        Value::Dictionary(dict) => {
+            for (key, value) in dict {
+                let path = if path.is_empty() {
+                    key
+                } else {
+                    format!("{path}.{key}")
+                };
+                flatten(value, &path, out, archives)?;
+            }
+        }
+        Value::Array(array) if array.is_empty() => writeln!(out, "{prefix}[]")?,
+        Value::Array(array) => {
+            for (i, value) in array.into_iter().enumerate() {
+                flatten(value, &format!("{path}[{i}]"), out, archives)?;
+            }
+        }
+        Value::String(s) if s.trim().is_empty() => writeln!(out, "{}", prefix.trim_end())?,
+        Value::String(s) => write_lines(out, &prefix, &s)?,
+        Value::Boolean(b) => writeln!(out, "{prefix}{b}")?,
+        Value::Integer(i) => writeln!(out, "{prefix}{i}")?,
+        Value::Real(r) => writeln!(out, "{prefix}{r}")?,
+        Value::Date(d) => writeln!(out, "{prefix}{}", d.to_xml_format())?,
+        Value::Uid(uid) => writeln!(out, "{prefix}UID {}", uid.get())?,
+        Value::Data(data) => {
+            writeln!(
+                out,
+                "{prefix}[data {}B]",
+                size_format::SizeFormatterSI::new(data.len() as u64)
+            )?;
+            let archive = ARCHIVE_MAGIC
+                .iter()
+                .find(|(magic, _)| data.starts_with(magic));
+            if let Some((_, extension)) = archive {
+                archives.push(Archive {
+                    path: path.to_owned(),
+                    extension,
+                    data,
+                });
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+#[async_trait]
+impl FileAdapter for PlistAdapter {
+    async fn adapt(
+        &self,
+        ai: AdaptInfo,
+        _detection_reason: &FileMatcher,
+    ) -> Result<AdaptedFilesIterBox> {
+        let AdaptInfo {
+            filepath_hint,
+            mut inp,
+            line_prefix,
+            archive_recursion_depth,
+            config,
+            postprocess,
+            ..
+        } = ai;
+        let s = stream! {
+            let mut content = Vec::new();
+            inp.read_to_end(&mut content).await?;
+            let plist = Value::from_reader(Cursor::new(content))
+                .with_context(|| format!("parsing property list {}", filepath_hint.display()))?;
+            let mut text = Vec::new();
+            let mut archives = Vec::new();
+            flatten(plist, "", &mut text, &mut archives)?;
+            yield Ok(AdaptInfo {
+                filepath_hint: PathBuf::from(format!("{}.txt", filepath_hint.to_string_lossy())),
+                is_real_file: false,
+                archive_recursion_depth,
+                inp: Box::pin(Cursor::new(text)),
+                line_prefix: line_prefix.clone(),
+                config: config.clone(),
+                postprocess,
+            });
+            for archive in archives {
+                yield Ok(AdaptInfo {
+                    filepath_hint: filepath_hint
+                        .join(format!("{}.{}", archive.path, archive.extension)),
+                    is_real_file: false,
+                    archive_recursion_depth: archive_recursion_depth + 1,
+                    inp: Box::pin(Cursor::new(archive.data)),
+                    line_prefix: format!("{}{}: ", line_prefix, archive.path),
+                    config: config.clone(),
+                    postprocess,
+                });
+            }
+        };
+        Ok(Box::pin(s))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use pretty_assertions::assert_eq;
+
+    #[tokio::test]
+    async fn xml() -> Result<()> {
+        assert_eq!(
+            adapt_test_file(&PlistAdapter::new(), "hello.plist", true).await?,
+            "PREFIX:CFBundleName: Hello
+PREFIX:CFBundleVersion: 1.2
+PREFIX:LSMinimumSystemVersion: 10.13
+PREFIX:Documents[0].Name: Notes
+PREFIX:Documents[0].Created: 2024-02-29T12:00:00Z
+PREFIX:Documents[1].Name: Multi
+PREFIX:Documents[1].Name: line
+PREFIX:Documents[1].Pinned: true
+PREFIX:Documents[1].Size: 42
+PREFIX:Empty: {}
+PREFIX:
+"
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn openstep() -> Result<()> {
+        assert_eq!(
+            adapt_test_file(&PlistAdapter::new(), "hello.openstep.plist", true).await?,
+            "PREFIX:Name: Hello
+PREFIX:Tags[0]: one
+PREFIX:Tags[1]: two words
+PREFIX:Nested.Key: value
+PREFIX:
+"
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn binary_with_nested_plist() -> Result<()> {
+        assert_eq!(
+            adapt_test_file(&PlistAdapter::new(), "hello.bplist", true).await?,
+            "PREFIX:Title: Outer
+PREFIX:Archived: [data 65B]
+PREFIX:
+PREFIX:Archived: Inner: secret value
+PREFIX:Archived: 
+"
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,143 @@
+use super::blocking::{EntrySender, SpooledFile, blocking_entries};
+use super::*;
+use crate::print_bytes;
+use anyhow::Result;
+use lazy_static::lazy_static;
+use unrar::Archive;
+
+static EXTENSIONS: &[&str] = &["rar"];
+static MIME_TYPES: &[&str] = &["application/vnd.rar", "application/x-rar"];
+
+lazy_static! {
+    static ref METADATA: AdapterMeta = AdapterMeta {
+        name: "rar".to_owned(),
+        version: 1,
+        description: "Reads a rar archive (RAR4 and RAR5, including solid archives) and recurses down into its contents".to_owned(),
+        recurses: true,
+        fast_matchers: EXTENSIONS
+            .iter()
+            .map(|s| FastFileMatcher::FileExtension(s.to_string()))
+            .collect(),
+        slow_matchers: Some(
+            MIME_TYPES
+                .iter()
+                .map(|s| FileMatcher::MimeType(s.to_string()))
+                .collect()
+        ),
+        keep_fast_matchers_if_accurate: true,
+        disabled_by_default: false
+    };
+}
+#[derive(Default, Clone)]
+pub struct RarAdapter;
+
+impl RarAdapter {
+    pub fn new() -> Self {
+        Self
+    }
+}
+impl GetMetadata for RarAdapter {
+    fn metadata(&self) -> &AdapterMeta {
+        &METADATA
+    }
+}
+
+fn extract_entries(ai: AdaptInfo, entries: &EntrySender) -> Result<()> {
+    // unrar can only read archives from a path
+    let file = SpooledFile::new(&ai.filepath_hint, ai.is_real_file, ai.inp)?;
+    let archive_name = PathBuf::from(ai.filepath_hint.file_name().unwrap_or_default());
+    let mut archive = Archive::new(file.path())
+        .open_for_processing()
+        .with_context(|| format!("opening rar archive {}", ai.filepath_hint.display()))?;
+    if archive.has_encrypted_headers() {
+        return entries.send_message(&archive_name, "skipping rar archive with encrypted headers");
+    }
+    // entries are extracted to disk one at a time since unrar can only unpack to a file or to memory
+    let tmp_dir = tempfile::tempdir()?;
+    let tmp_entry = tmp_dir.path().join("entry");
+    while let Some(header) = archive.read_header()? {
+        let entry = header.entry();
+        let path = entry.filename.clone();
+        if entry.is_directory() {
+            archive = header.skip()?;
+            continue;
+        }
+        if entry.is_encrypted() {
+            entries.send_message(&path, "skipping encrypted rar entry")?;
+            archive = header.skip()?;
+            continue;
+        }
+        debug!(
+            "{}{}|{}: {}",
+            ai.line_prefix,
+            ai.filepath_hint.display(),
+            path.display(),
+            print_bytes(entry.unpacked_size as f64)
+        );
+        match header.extract_to(&tmp_entry) {
+            Ok(next) => {
+                archive = next;
+                entries.send(path, std::fs::File::open(&tmp_entry)?)?;
+                std::fs::remove_file(&tmp_entry)?;
+            }
+            Err(e) => {
+                // unrar can't continue with the following entries after a failed extraction
+                return entries.send_message(&path, &format!("could not extract rar entry: {e}"));
+            }
+        }
+    }
+    Ok(())
+}
+
+#[async_trait]
+impl FileAdapter for RarAdapter {
+    async fn adapt(
+        &self,
+        ai: AdaptInfo,
+        _detection_reason: &FileMatcher,
+    ) -> Result<AdaptedFilesIterBox> {
+        Ok(blocking_entries(ai, extract_entries))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use pretty_assertions::assert_eq;
+
+    #[tokio::test]
+    async fn solid() -> Result<()> {
+        // the later entries are compressed as references to the data of the earlier ones
+        assert_eq!(
+            adapt_test_file(&RarAdapter::new(), "solid.rar", false).await?,
+            "PREFIX:first.txt: solid archives share one dictionary
+PREFIX:first.txt: 
+PREFIX:second.txt: solid archives share one dictionary
+PREFIX:second.txt: across entries
+PREFIX:second.txt: 
+PREFIX:docs/third.txt: across entries
+PREFIX:docs/third.txt: 
+"
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn encrypted_entry() -> Result<()> {
+        assert_eq!(
+            adapt_test_file(&RarAdapter::new(), "crypted.rar", false).await?,
+            "PREFIX:.gitignore: [rga: skipping encrypted rar entry]\nPREFIX:.gitignore: \n"
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn encrypted_headers() -> Result<()> {
+        assert_eq!(
+            adapt_test_file(&RarAdapter::new(), "encrypted-headers.rar", false).await?,
+            "PREFIX:encrypted-headers.rar: [rga: skipping rar archive with encrypted headers]\nPREFIX:encrypted-headers.rar: \n"
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,187 @@
+use crate::adapted_iter::one_file;
+
+use super::*;
+
+use anyhow::Result;
+use lazy_static::lazy_static;
+use tokio::io::AsyncReadExt;
+
+static EXTENSIONS: &[&str] = &["rpm"];
+
+lazy_static! {
+    static ref METADATA: AdapterMeta = AdapterMeta {
+        name: "rpm".to_owned(),
+        version: 1,
+        description:
+            "Skips the headers of an RPM package and passes its compressed cpio payload on to the decompress and cpio adapters."
+                .to_owned(),
+        recurses: true,
+        fast_matchers: EXTENSIONS
+            .iter()
+            .map(|s| FastFileMatcher::FileExtension(s.to_string()))
+            .collect(),
+        slow_matchers: Some(vec![FileMatcher::MimeType("application/x-rpm".to_owned())]),
+        disabled_by_default: false,
+        keep_fast_matchers_if_accurate: true
+    };
+}
+#[derive(Default, Clone)]
+pub struct RpmAdapter;
+
+impl RpmAdapter {
+    pub fn new() -> Self {
+        Self
+    }
+}
+impl GetMetadata for RpmAdapter {
+    fn metadata(&self) -> &AdapterMeta {
+        &METADATA
+    }
+}
+
+const LEAD_SIZE: usize = 96;
+const LEAD_MAGIC: &[u8] = &[0xed, 0xab, 0xee, 0xdb];
+const HEADER_MAGIC: &[u8] = &[0x8e, 0xad, 0xe8];
+const RPMTAG_PAYLOADCOMPRESSOR: i32 = 1125;
+const RPM_STRING_TYPE: i32 = 6;
+/// Same limits as rpm itself, so that a corrupt header doesn't make us allocate everything
+const MAX_HEADER_TAGS: usize = 0xffff;
+const MAX_HEADER_DATA: usize = 0x0fff_ffff;
+
+/// A header structure, as used for both the signature and the package header
+struct RpmHeader {
+    /// (tag, type, offset, count)
+    index: Vec<(i32, i32, i32, i32)>,
+    store: Vec<u8>,
+}
+
+impl RpmHeader {
+    async fn read(inp: &mut ReadBox) -> Result<Self> {
+        let mut intro = [0u8; 16];
+        inp.read_exact(&mut intro).await?;
+        if !intro.starts_with(HEADER_MAGIC) {
+            return Err(format_err!("invalid rpm header magic"));
+        }
+        let nindex = u32::from_be_bytes(intro[8..12].try_into()?) as usize;
+        let hsize = u32::from_be_bytes(intro[12..16].try_into()?) as usize;
+        if nindex > MAX_HEADER_TAGS || hsize > MAX_HEADER_DATA {
+            return Err(format_err!(
+                "rpm header too large ({nindex} tags, {hsize} bytes)"
+            ));
+        }
+        let mut index = vec![0u8; nindex * 16];
+        inp.read_exact(&mut index).await?;
+        let mut store = vec![0u8; hsize];
+        inp.read_exact(&mut store).await?;
+        let index = index
+            .chunks_exact(16)
+            .map(|e| {
+                let int = |i: usize| i32::from_be_bytes(e[i..i + 4].try_into().unwrap());
+                (int(0), int(4), int(8), int(12))
+            })
+            .collect();
+        Ok(Self { index, store })
+    }
+
+    fn byte_len(&self) -> usize {
+        16 + self.index.len() * 16 + self.store.len()
+    }
+
+    fn get_string(&self, tag: i32) -> Option<String> {
+        let (_, _, offset, _) = self
+            .index
+            .iter()
+            .find(|(t, typ, _, _)| *t == tag && *typ == RPM_STRING_TYPE)?;
+        let data = self.store.get(*offset as usize..)?;
+        let end = data.iter().position(|b| *b == 0)?;
+        Some(String::from_utf8_lossy(&data[..end]).into_owned())
+    }
+}
+
+/// Reads the lead, signature and header of the package and returns the file extension of the compressed payload.
+async fn skip_headers(inp: &mut ReadBox) -> Result<&'static str> {
+    let mut lead = [0u8; LEAD_SIZE];
+    inp.read_exact(&mut lead).await?;
+    if !lead.starts_with(LEAD_MAGIC) {
+        return Err(format_err!("not an rpm file"));
+    }
+    let signature = RpmHeader::read(inp)
+        .await
+        .context("reading rpm signature")?;
+    // the signature is padded to a multiple of 8 bytes
+    let padding = (8 - signature.byte_len() % 8) % 8;
+    inp.read_exact(&mut vec![0u8; padding]).await?;
+    let header = RpmHeader::read(inp).await.context("reading rpm header")?;
+    // rpm defaults to gzip if the tag is missing
+    let compressor = header
+        .get_string(RPMTAG_PAYLOADCOMPRESSOR)
+        .unwrap_or_else(|| "gzip".to_owned());
+    Ok(match compressor.as_str() {
+        "gzip" => "gz",
+        "bzip2" => "bz2",
+        "xz" => "xz",
+        "lzma" => "lzma",
+        "zstd" => "zst",
+        other => Err(format_err!("unsupported rpm payload compressor {}", other))?,
+    })
+}
+
+#[async_trait]
+impl FileAdapter for RpmAdapter {
+    async fn adapt(
+        &self,
+        ai: AdaptInfo,
+        _detection_reason: &FileMatcher,
+    ) -> Result<AdaptedFilesIterBox> {
+        let mut inp = ai.inp;
+        let extension = skip_headers(&mut inp)
+            .await
+            .with_context(|| format!("reading rpm {}", ai.filepath_hint.display()))?;
+        Ok(one_file(AdaptInfo {
+            filepath_hint: ai.filepath_hint.with_extension(format!("cpio.{extension}")),
+            is_real_file: false,
+            archive_recursion_depth: ai.archive_recursion_depth + 1,
+            inp,
+            line_prefix: ai.line_prefix,
+            config: ai.config.clone(),
+            postprocess: ai.postprocess,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::preproc::loop_adapt;
+    use crate::test_utils::*;
+    use pretty_assertions::assert_eq;
+    use tokio::fs::File;
+
+    #[tokio::test]
+    async fn payload() -> Result<()> {
+        let filepath = test_data_dir().join("hello.rpm");
+        let (a, d) = simple_adapt_info(&filepath, Box::pin(File::open(&filepath).await?));
+        let o = adapted_to_vec(loop_adapt(&RpmAdapter::new(), d, a).await?).await?;
+        assert_eq!(
+            String::from_utf8(o)?,
+            "PREFIX:./usr/share/doc/hello/README: hello from an rpm payload
+PREFIX:./usr/share/doc/hello/README: 
+"
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn oversized_header() -> Result<()> {
+        let mut data = std::fs::read(test_data_dir().join("hello.rpm"))?;
+        // claim that the signature header has 4 GiB of data
+        data[LEAD_SIZE + 12..LEAD_SIZE + 16].copy_from_slice(&[0xff; 4]);
+        let mut inp: ReadBox = Box::pin(std::io::Cursor::new(data));
+        let e = skip_headers(&mut inp).await.unwrap_err();
+        assert_eq!(
+            format!("{e:#}"),
+            "reading rpm signature: rpm header too large (1 tags, 4294967295 bytes)"
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,282 @@
+use super::writing::WritingFileAdapter;
+use super::*;
+use anyhow::Result;
+use lazy_static::lazy_static;
+use std::io::{BufWriter, Read, Write};
+use tokio::io::AsyncWrite;
+use tokio_util::io::SyncIoBridge;
+
+static EXTENSIONS: &[&str] = &["rtf"];
+static MIME_TYPES: &[&str] = &["application/rtf", "text/rtf"];
+/// Groups that don't contain document text, even if they are not marked with `\*`
+static SKIPPED_DESTINATIONS: &[&str] = &[
+    "colortbl",
+    "datastore",
+    "fldinst",
+    "fonttbl",
+    "info",
+    "listtable",
+    "listoverridetable",
+    "object",
+    "pict",
+    "rsidtbl",
+    "stylesheet",
+    "themedata",
+    "xmlnstbl",
+];
+
+lazy_static! {
+    static ref METADATA: AdapterMeta = AdapterMeta {
+        name: "rtf".to_owned(),
+        version: 1,
+        description:
+            "Extracts the text of rich text format (RTF) documents without their control words"
+                .to_owned(),
+        recurses: false,
+        fast_matchers: EXTENSIONS
+            .iter()
+            .map(|s| FastFileMatcher::FileExtension(s.to_string()))
+            .collect(),
+        slow_matchers: Some(
+            MIME_TYPES
+                .iter()
+                .map(|s| FileMatcher::MimeType(s.to_string()))
+                .collect()
+        ),
+        keep_fast_matchers_if_accurate: true,
+        disabled_by_default: false
+    };
+}
+
+#[derive(Default, Clone)]
+pub struct RtfAdapter;
+
+impl RtfAdapter {
+    pub fn new() -> Self {
+        Self
+    }
+}
+impl GetMetadata for RtfAdapter {
+    fn metadata(&self) -> &AdapterMeta {
+        &METADATA
+    }
+}
+
+#[derive(Clone, Copy)]
+struct GroupState {
+    skip: bool,
+    /// the number of fallback characters after a `\u` character
+    unicode_skip: usize,
+}
+
+struct RtfParser<'a> {
+    data: &'a [u8],
+    pos: usize,
+    text: String,
+    /// `\'hh` bytes, which are decoded together since they can be multi-byte characters
+    bytes: Vec<u8>,
+    encoding: &'static encoding_rs::Encoding,
+}
+
+impl RtfParser<'_> {
+    fn flush_bytes(&mut self) {
+        if !self.bytes.is_empty() {
+            let (decoded, _) = self.encoding.decode_without_bom_handling(&self.bytes);
+            self.text.push_str(&decoded);
+            self.bytes.clear();
+        }
+    }
+
+    fn push(&mut self, state: &GroupState, text: &str) {
+        if !state.skip {
+            self.flush_bytes();
+            self.text.push_str(text);
+        }
+    }
+
+    /// Skips `count` characters after a `\u` control word, either plain characters or `\'hh` escapes
+    fn skip_fallback(&mut self, count: usize) {
+        for _ in 0..count {
+            match self.data.get(self.pos) {
+                Some(b'\\') if self.data.get(self.pos + 1) == Some(&b'\'') => self.pos += 4,
+                Some(b'\\' | b'{' | b'}') | None => break,
+                Some(_) => self.pos += 1,
+            }
+        }
+    }
+
+    fn control_word(&mut self, word: &str, param: Option<i32>, state: &mut GroupState) {
+        match word {
+            "par" | "line" | "sect" | "page" | "row" => self.push(state, "\n"),
+            "tab" | "cell" => self.push(state, "\t"),
+            "emdash" => self.push(state, "\u{2014}"),
+            "endash" => self.push(state, "\u{2013}"),
+            "bullet" => self.push(state, "\u{2022}"),
+            "lquote" => self.push(state, "\u{2018}"),
+            "rquote" => self.push(state, "\u{2019}"),
+            "ldblquote" => self.push(state, "\u{201c}"),
+            "rdblquote" => self.push(state, "\u{201d}"),
+            "ansicpg" => {
+                if let Some(encoding) = param
+                    .and_then(|p| u16::try_from(p).ok())
+                    .and_then(codepage::to_encoding)
+                {
+                    self.encoding = encoding;
+                }
+            }
+            "uc" => state.unicode_skip = param.unwrap_or(1).max(0) as usize,
+            "u" => {
+                // negative for code points above 32767
+                let c = param.unwrap_or(0) as i64;
+                let c = if c < 0 { c + 65536 } else { c };
+                let c = char::from_u32(c as u32).unwrap_or(char::REPLACEMENT_CHARACTER);
+                self.push(state, c.encode_utf8(&mut [0; 4]));
+                self.skip_fallback(state.unicode_skip);
+            }
+            "bin" => self.pos += param.unwrap_or(0).max(0) as usize,
+            word if SKIPPED_DESTINATIONS.contains(&word) => state.skip = true,
+            _ => {}
+        }
+    }
+
+    fn parse(&mut self) {
+        let mut stack = Vec::new();
+        let mut state = GroupState {
+            skip: false,
+            unicode_skip: 1,
+        };
+        while let Some(&c) = self.data.get(self.pos) {
+            self.pos += 1;
+            match c {
+                b'{' => stack.push(state),
+                b'}' => state = stack.pop().unwrap_or(state),
+                b'\\' => {
+                    let Some(&next) = self.data.get(self.pos) else {
+                        break;
+                    };
+                    self.pos += 1;
+                    match next {
+                        b'a'..=b'z' | b'A'..=b'Z' => {
+                            let start = self.pos - 1;
+                            while self.data.get(self.pos).is_some_and(u8::is_ascii_alphabetic) {
+                                self.pos += 1;
+                            }
+                            let word = String::from_utf8_lossy(&self.data[start..self.pos]);
+                            let start = self.pos;
+                            if self.data.get(self.pos) == Some(&b'-') {
+                                self.pos += 1;
+                            }
+                            while self.data.get(self.pos).is_some_and(u8::is_ascii_digit) {
+                                self.pos += 1;
+                            }
+                            let param = std::str::from_utf8(&self.data[start..self.pos])
+                                .ok()
+                                .and_then(|p| p.parse().ok());
+                            // a space only delimits the control word
+                            if self.data.get(self.pos) == Some(&b' ') {
+                                self.pos += 1;
+                            }
+                            self.control_word(&word, param, &mut state);
+                        }
+                        b'\'' => {
+                            let hex = self.data.get(self.pos..self.pos + 2).unwrap_or_default();
+                            self.pos += 2;
+                            if let Some(byte) = std::str::from_utf8(hex)
+                                .ok()
+                                .and_then(|h| u8::from_str_radix(h, 16).ok())
+                                && !state.skip
+                            {
+                                self.bytes.push(byte);
+                            }
+                        }
+                        // ignorable destination
+                        b'*' => state.skip = true,
+                        b'~' => self.push(&state, "\u{a0}"),
+                        b'_' => self.push(&state, "-"),
+                        b'\n' | b'\r' => self.push(&state, "\n"),
+                        b'\\' | b'{' | b'}' => self.push(&state, &(next as char).to_string()),
+                        // e.g. optional hyphens and formula characters
+                        _ => {}
+                    }
+                }
+                // line breaks in the source are not part of the text
+                b'\r' | b'\n' => {}
+                // unescaped 8-bit characters in the document code page
+                0x80.. => {
+                    if !state.skip {
+                        self.bytes.push(c);
+                    }
+                }
+                _ => {
+                    if !state.skip {
+                        self.flush_bytes();
+                        self.text.push(c as char);
+                    }
+                }
+            }
+        }
+        self.flush_bytes();
+    }
+}
+
+/// Returns the plain text of an rtf document
+fn rtf_to_text(data: &[u8]) -> String {
+    let mut parser = RtfParser {
+        data,
+        pos: 0,
+        text: String::new(),
+        bytes: Vec::new(),
+        encoding: encoding_rs::WINDOWS_1252,
+    };
+    parser.parse();
+    parser.text
+}
+
+fn synchronous_dump_rtf(ai: AdaptInfo, out: impl Write) -> Result<()> {
+    let mut data = Vec::new();
+    SyncIoBridge::new(ai.inp).read_to_end(&mut data)?;
+    let mut out = BufWriter::new(out);
+    for line in rtf_to_text(&data).lines() {
+        writeln!(out, "{}", line.trim_end())?;
+    }
+    out.flush()?;
+    Ok(())
+}
+
+#[async_trait]
+impl WritingFileAdapter for RtfAdapter {
+    async fn adapt_write(
+        ai: AdaptInfo,
+        _detection_reason: &FileMatcher,
+        oup: Pin<Box<dyn AsyncWrite + Send>>,
+    ) -> Result<()> {
+        let oup_sync = SyncIoBridge::new(oup);
+        tokio::task::spawn_blocking(|| synchronous_dump_rtf(ai, oup_sync))
+            .await?
+            .context("in synchronous rtf task")?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{preproc::loop_adapt, test_utils::*};
+    use pretty_assertions::assert_eq;
+
+    #[tokio::test]
+    async fn simple() -> Result<()> {
+        let filepath = test_data_dir().join("hello.rtf");
+        let (a, d) = simple_fs_adapt_info(&filepath).await?;
+        let o = adapted_to_vec(loop_adapt(&RtfAdapter::new(), d, a).await?).await?;
+        assert_eq!(
+            String::from_utf8(o)?,
+            "PREFIX:Hello world!
+PREFIX:Caf\u{e9} \u{201c}quoted\u{201d} \u{3b1}\u{3b2} {braces}
+PREFIX:one\ttwo
+PREFIX:
+"
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,111 @@
+use super::blocking::{SpooledFile, blocking_entries};
+use super::*;
+use crate::print_bytes;
+use anyhow::Result;
+use lazy_static::lazy_static;
+use sevenz_rust::{Password, SevenZReader};
+
+static EXTENSIONS: &[&str] = &["7z"];
+
+lazy_static! {
+    static ref METADATA: AdapterMeta = AdapterMeta {
+        name: "sevenzip".to_owned(),
+        version: 1,
+        description: "Reads a 7z archive and recurses down into its contents".to_owned(),
+        recurses: true,
+        fast_matchers: EXTENSIONS
+            .iter()
+            .map(|s| FastFileMatcher::FileExtension(s.to_string()))
+            .collect(),
+        slow_matchers: Some(vec![FileMatcher::MimeType(
+            "application/x-7z-compressed".to_owned()
+        )]),
+        keep_fast_matchers_if_accurate: true,
+        disabled_by_default: false
+    };
+}
+#[derive(Default, Clone)]
+pub struct SevenZipAdapter;
+
+impl SevenZipAdapter {
+    pub fn new() -> Self {
+        Self
+    }
+}
+impl GetMetadata for SevenZipAdapter {
+    fn metadata(&self) -> &AdapterMeta {
+        &METADATA
+    }
+}
+
+#[async_trait]
+impl FileAdapter for SevenZipAdapter {
+    async fn adapt(
+        &self,
+        ai: AdaptInfo,
+        _detection_reason: &FileMatcher,
+    ) -> Result<AdaptedFilesIterBox> {
+        Ok(blocking_entries(ai, |ai, entries| {
+            // the 7z index is at the end of the file, so we need to be able to seek
+            let file = SpooledFile::new(&ai.filepath_hint, ai.is_real_file, ai.inp)?;
+            let mut archive = SevenZReader::open(file.path(), Password::empty())
+                .with_context(|| format!("opening 7z archive {}", ai.filepath_hint.display()))?;
+            let mut send_err = None;
+            archive.for_each_entries(|entry, reader| {
+                if entry.is_directory() {
+                    return Ok(true);
+                }
+                debug!(
+                    "{}{}|{}: {}",
+                    ai.line_prefix,
+                    ai.filepath_hint.display(),
+                    entry.name(),
+                    print_bytes(entry.size() as f64)
+                );
+                if let Err(e) = entries.send(entry.name(), reader) {
+                    send_err = Some(e);
+                    return Ok(false);
+                }
+                Ok(true)
+            })?;
+            match send_err {
+                Some(e) => Err(e),
+                None => Ok(()),
+            }
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{preproc::loop_adapt, test_utils::*};
+    use pretty_assertions::assert_eq;
+    use tokio::fs::File;
+
+    const EXPECTED: &str = "PREFIX:hello.txt: hello world
+PREFIX:hello.txt: this is a 7z archive
+PREFIX:hello.txt: 
+PREFIX:dir/hello.gz: hello
+PREFIX:dir/hello.gz: 
+";
+
+    #[tokio::test]
+    async fn simple_fs() -> Result<()> {
+        let filepath = test_data_dir().join("hello.7z");
+        let (a, d) = simple_fs_adapt_info(&filepath).await?;
+        let o = adapted_to_vec(loop_adapt(&SevenZipAdapter::new(), d, a).await?).await?;
+        assert_eq!(String::from_utf8(o)?, EXPECTED);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn spooled() -> Result<()> {
+        // not a real file, e.g. a 7z file within some other archive
+        let filepath = test_data_dir().join("hello.7z");
+        let (a, d) = simple_adapt_info(&filepath, Box::pin(File::open(&filepath).await?));
+        let o = adapted_to_vec(loop_adapt(&SevenZipAdapter::new(), d, a).await?).await?;
+        assert_eq!(String::from_utf8(o)?, EXPECTED);
+        Ok(())
+    }
+}
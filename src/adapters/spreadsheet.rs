@@ -0,0 +1,317 @@
+use super::writing::WritingFileAdapter;
+use super::xml::{column_name, write_lines};
+use super::*;
+use anyhow::Result;
+use calamine::{Data, Reader, SheetType, open_workbook_auto_from_rs};
+use lazy_static::lazy_static;
+use std::io::{BufWriter, Cursor, Read, Write};
+use tokio::io::AsyncWrite;
+use tokio_util::io::SyncIoBridge;
+
+static EXTENSIONS: &[&str] = &[
+    "xlsx", "xlsm", "xltx", "xltm", "xlsb", "xls", "xla", "ods", "ots",
+];
+static MIME_TYPES: &[&str] = &[
+    "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+    "application/vnd.ms-excel",
+    "application/vnd.ms-excel.sheet.macroenabled.12",
+    "application/vnd.ms-excel.sheet.binary.macroenabled.12",
+    "application/vnd.oasis.opendocument.spreadsheet",
+    "application/vnd.oasis.opendocument.spreadsheet-template",
+];
+
+lazy_static! {
+    static ref METADATA: AdapterMeta = AdapterMeta {
+        name: "spreadsheet".to_owned(),
+        version: 1,
+        description: "Outputs each non-empty cell of a spreadsheet (xlsx, xls, xlsb, ods) as `Sheet1!B12: value`.\nWith --rga-spreadsheet-formulas, the formulas of computed cells are included as well.".to_owned(),
+        recurses: false,
+        fast_matchers: EXTENSIONS
+            .iter()
+            .map(|s| FastFileMatcher::FileExtension(s.to_string()))
+            .collect(),
+        slow_matchers: Some(
+            MIME_TYPES
+                .iter()
+                .map(|s| FileMatcher::MimeType(s.to_string()))
+                .collect()
+        ),
+        keep_fast_matchers_if_accurate: true,
+        disabled_by_default: false
+    };
+}
+#[derive(Default, Clone)]
+pub struct SpreadsheetAdapter;
+
+impl SpreadsheetAdapter {
+    pub fn new() -> Self {
+        Self
+    }
+}
+impl GetMetadata for SpreadsheetAdapter {
+    fn metadata(&self) -> &AdapterMeta {
+        &METADATA
+    }
+}
+
+fn format_cell(cell: &Data) -> String {
+    match cell {
+        Data::Bool(true) => "TRUE".to_owned(),
+        Data::Bool(false) => "FALSE".to_owned(),
+        // dates are stored as the number of days since 1900, show them the way they are displayed instead
+        Data::DateTime(d) => match d.as_datetime() {
+            Some(d) => {
+                let d = d.to_string();
+                d.strip_suffix(" 00:00:00").unwrap_or(&d).to_owned()
+            }
+            None => d.to_string(),
+        },
+        cell => cell.to_string(),
+    }
+}
+
+/// ODF formulas start with the namespace of their syntax, e.g. `of:=[.A1]*2`
+fn strip_formula_namespace(formula: &str) -> &str {
+    match formula.split_once(':') {
+        Some((namespace, rest))
+            if rest.starts_with('=')
+                && !namespace.is_empty()
+                && namespace.bytes().all(|b| b.is_ascii_lowercase()) =>
+        {
+            rest
+        }
+        _ => formula,
+    }
+}
+
+fn synchronous_dump_spreadsheet(ai: AdaptInfo, out: impl Write) -> Result<()> {
+    let include_formulas = ai.config.adapter_options.spreadsheet_formulas;
+    // calamine reads the whole workbook into memory anyways, and detecting the format needs to seek
+    let mut data = Vec::new();
+    SyncIoBridge::new(ai.inp).read_to_end(&mut data)?;
+    let mut workbook = open_workbook_auto_from_rs(Cursor::new(&data[..]))
+        .with_context(|| format!("opening spreadsheet {}", ai.filepath_hint.display()))?;
+    let mut out = BufWriter::new(out);
+    let sheets: Vec<String> = workbook
+        .sheets_metadata()
+        .iter()
+        .filter(|s| s.typ == SheetType::WorkSheet)
+        .map(|s| s.name.clone())
+        .collect();
+    for sheet in sheets {
+        let values = workbook
+            .worksheet_range(&sheet)
+            .with_context(|| format!("reading sheet {sheet}"))?;
+        let formulas = if include_formulas {
+            Some(
+                workbook
+                    .worksheet_formula(&sheet)
+                    .with_context(|| format!("reading formulas of sheet {sheet}"))?,
+            )
+        } else {
+            None
+        };
+        let Some((start_row, start_column)) = values.start() else {
+            continue;
+        };
+        for (row, column, cell) in values.used_cells() {
+            let (row, column) = (start_row + row as u32, start_column + column as u32);
+            let mut value = format_cell(cell);
+            if let Some(formula) = formulas
+                .as_ref()
+                .and_then(|f| f.get_value((row, column)))
+                .map(|f| strip_formula_namespace(f))
+                .filter(|f| !f.is_empty())
+            {
+                let eq = if formula.starts_with('=') { "" } else { "=" };
+                value = format!("{value} [{eq}{formula}]");
+            }
+            let prefix = format!("{sheet}!{}{}: ", column_name(column), row + 1);
+            write_lines(&mut out, &prefix, &value)?;
+        }
+    }
+    out.flush()?;
+    Ok(())
+}
+
+#[async_trait]
+impl WritingFileAdapter for SpreadsheetAdapter {
+    async fn adapt_write(
+        ai: AdaptInfo,
+        _detection_reason: &FileMatcher,
+        oup: Pin<Box<dyn AsyncWrite + Send>>,
+    ) -> Result<()> {
+        let oup_sync = SyncIoBridge::new(oup);
+        tokio::task::spawn_blocking(|| synchronous_dump_spreadsheet(ai, oup_sync))
+            .await?
+            .context("in synchronous spreadsheet task")?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{preproc::loop_adapt, test_utils::*};
+    use ::zip::write::{SimpleFileOptions, ZipWriter};
+    use pretty_assertions::assert_eq;
+    use tokio::fs::File;
+
+    /// Builds a zip file in memory
+    fn zip_parts(parts: &[(&str, &str)]) -> Result<Vec<u8>> {
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, content) in parts {
+            zip.start_file(*name, SimpleFileOptions::default())?;
+            zip.write_all(content.as_bytes())?;
+        }
+        Ok(zip.finish()?.into_inner())
+    }
+
+    fn generated_xlsx() -> Result<Vec<u8>> {
+        const NS: &str = r#"xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main" xmlns:r="http://schemas.openxmlformats.org/officeDocument/2006/relationships""#;
+        const REL: &str = "http://schemas.openxmlformats.org/officeDocument/2006/relationships";
+        zip_parts(&[
+            (
+                "[Content_Types].xml",
+                r#"<Types xmlns="http://schemas.openxmlformats.org/package/2006/content-types"><Default Extension="rels" ContentType="application/vnd.openxmlformats-package.relationships+xml"/><Default Extension="xml" ContentType="application/xml"/><Override PartName="/xl/workbook.xml" ContentType="application/vnd.openxmlformats-officedocument.spreadsheetml.sheet.main+xml"/><Override PartName="/xl/worksheets/sheet1.xml" ContentType="application/vnd.openxmlformats-officedocument.spreadsheetml.worksheet+xml"/><Override PartName="/xl/worksheets/sheet2.xml" ContentType="application/vnd.openxmlformats-officedocument.spreadsheetml.worksheet+xml"/><Override PartName="/xl/sharedStrings.xml" ContentType="application/vnd.openxmlformats-officedocument.spreadsheetml.sharedStrings+xml"/><Override PartName="/xl/styles.xml" ContentType="application/vnd.openxmlformats-officedocument.spreadsheetml.styles+xml"/></Types>"#,
+            ),
+            (
+                "_rels/.rels",
+                &format!(
+                    r#"<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships"><Relationship Id="rId1" Type="{REL}/officeDocument" Target="xl/workbook.xml"/></Relationships>"#
+                ),
+            ),
+            (
+                "xl/_rels/workbook.xml.rels",
+                &format!(
+                    r#"<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships"><Relationship Id="rId1" Type="{REL}/worksheet" Target="worksheets/sheet1.xml"/><Relationship Id="rId2" Type="{REL}/worksheet" Target="worksheets/sheet2.xml"/><Relationship Id="rId3" Type="{REL}/sharedStrings" Target="sharedStrings.xml"/><Relationship Id="rId4" Type="{REL}/styles" Target="styles.xml"/></Relationships>"#
+                ),
+            ),
+            (
+                "xl/workbook.xml",
+                &format!(
+                    r#"<workbook {NS}><sheets><sheet name="Greetings" sheetId="1" r:id="rId1"/><sheet name="Numbers" sheetId="2" r:id="rId2"/></sheets></workbook>"#
+                ),
+            ),
+            (
+                "xl/sharedStrings.xml",
+                &format!(
+                    r#"<sst {NS} count="3" uniqueCount="3"><si><t>hello</t></si><si><r><t>rich </t></r><r><t>text</t></r></si><si><t>two
+lines</t></si></sst>"#
+                ),
+            ),
+            (
+                "xl/styles.xml",
+                &format!(
+                    r#"<styleSheet {NS}><cellXfs count="2"><xf numFmtId="0"/><xf numFmtId="14" applyNumberFormat="1"/></cellXfs></styleSheet>"#
+                ),
+            ),
+            (
+                "xl/worksheets/sheet1.xml",
+                &format!(
+                    r#"<worksheet {NS}><sheetData><row r="1"><c r="A1" t="s"><v>0</v></c><c r="B1" t="s"><v>1</v></c></row><row r="12"><c r="B12" t="s"><v>2</v></c><c r="C12" t="inlineStr"><is><t>inline string</t></is></c></row></sheetData></worksheet>"#
+                ),
+            ),
+            (
+                "xl/worksheets/sheet2.xml",
+                &format!(
+                    r#"<worksheet {NS}><sheetData><row r="1"><c r="A1"><v>42</v></c><c r="B1"><v>3.5</v></c><c r="C1"><f>A1*B1</f><v>147</v></c><c r="D1" t="b"><v>1</v></c><c r="E1" t="e"><v>#DIV/0!</v></c><c r="F1" s="1"><v>44927</v></c></row></sheetData></worksheet>"#
+                ),
+            ),
+        ])
+    }
+
+    fn generated_ods() -> Result<Vec<u8>> {
+        zip_parts(&[
+            ("mimetype", "application/vnd.oasis.opendocument.spreadsheet"),
+            (
+                "META-INF/manifest.xml",
+                r#"<manifest:manifest xmlns:manifest="urn:oasis:names:tc:opendocument:xmlns:manifest:1.0" manifest:version="1.2"><manifest:file-entry manifest:full-path="/" manifest:media-type="application/vnd.oasis.opendocument.spreadsheet"/><manifest:file-entry manifest:full-path="content.xml" manifest:media-type="text/xml"/></manifest:manifest>"#,
+            ),
+            (
+                "content.xml",
+                r#"<office:document-content xmlns:office="urn:oasis:names:tc:opendocument:xmlns:office:1.0" xmlns:table="urn:oasis:names:tc:opendocument:xmlns:table:1.0" xmlns:text="urn:oasis:names:tc:opendocument:xmlns:text:1.0" office:version="1.2"><office:body><office:spreadsheet><table:table table:name="Greetings"><table:table-row><table:table-cell office:value-type="string"><text:p>hello</text:p></table:table-cell><table:table-cell table:number-columns-repeated="2"/><table:table-cell office:value-type="string"><text:p>world</text:p></table:table-cell></table:table-row><table:table-row table:number-rows-repeated="10"><table:table-cell table:number-columns-repeated="4"/></table:table-row><table:table-row><table:table-cell/><table:table-cell office:value-type="float" office:value="42"><text:p>42</text:p></table:table-cell><table:table-cell table:formula="of:=[.B12]*2" office:value-type="float" office:value="84"><text:p>84</text:p></table:table-cell><table:table-cell office:value-type="boolean" office:boolean-value="true"><text:p>TRUE</text:p></table:table-cell></table:table-row></table:table></office:spreadsheet></office:body></office:document-content>"#,
+            ),
+        ])
+    }
+
+    async fn adapt_bytes(name: &str, data: Vec<u8>, formulas: bool) -> Result<String> {
+        let (mut a, d) = simple_adapt_info(&PathBuf::from(name), Box::pin(Cursor::new(data)));
+        a.config.adapter_options.spreadsheet_formulas = formulas;
+        let o = adapted_to_vec(loop_adapt(&SpreadsheetAdapter::new(), d, a).await?).await?;
+        Ok(String::from_utf8(o)?)
+    }
+
+    #[tokio::test]
+    async fn xlsx() -> Result<()> {
+        assert_eq!(
+            adapt_bytes("hello.xlsx", generated_xlsx()?, false).await?,
+            "PREFIX:Greetings!A1: hello
+PREFIX:Greetings!B1: rich text
+PREFIX:Greetings!B12: two
+PREFIX:Greetings!B12: lines
+PREFIX:Greetings!C12: inline string
+PREFIX:Numbers!A1: 42
+PREFIX:Numbers!B1: 3.5
+PREFIX:Numbers!C1: 147
+PREFIX:Numbers!D1: TRUE
+PREFIX:Numbers!E1: #DIV/0!
+PREFIX:Numbers!F1: 2023-01-01
+PREFIX:
+"
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn xlsx_formulas() -> Result<()> {
+        let o = adapt_bytes("hello.xlsx", generated_xlsx()?, true).await?;
+        assert!(o.contains("PREFIX:Numbers!C1: 147 [=A1*B1]\n"), "{o}");
+        assert!(o.contains("PREFIX:Numbers!B1: 3.5\n"), "{o}");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn ods() -> Result<()> {
+        assert_eq!(
+            adapt_bytes("hello.ods", generated_ods()?, true).await?,
+            "PREFIX:Greetings!A1: hello
+PREFIX:Greetings!D1: world
+PREFIX:Greetings!B12: 42
+PREFIX:Greetings!C12: 84 [=[.B12]*2]
+PREFIX:Greetings!D12: TRUE
+PREFIX:
+"
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn xls() -> Result<()> {
+        let filepath = test_data_dir().join("hello.xls");
+        let (mut a, d) = simple_adapt_info(&filepath, Box::pin(File::open(&filepath).await?));
+        a.config.adapter_options.spreadsheet_formulas = true;
+        let o = adapted_to_vec(loop_adapt(&SpreadsheetAdapter::new(), d, a).await?).await?;
+        assert_eq!(
+            String::from_utf8(o)?,
+            "PREFIX:Greetings!A1: hello
+PREFIX:Greetings!B1: world
+PREFIX:Greetings!C1: TRUE
+PREFIX:Greetings!A2: 42
+PREFIX:Greetings!B2: 3.5
+PREFIX:Greetings!C2: 147 [=A2*B2]
+PREFIX:
+"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn cell_references() {
+        assert_eq!(column_name(0), "A");
+        assert_eq!(column_name(25), "Z");
+        assert_eq!(column_name(26), "AA");
+        assert_eq!(column_name(701), "ZZ");
+        assert_eq!(column_name(702), "AAA");
+    }
+}
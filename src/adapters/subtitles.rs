@@ -0,0 +1,223 @@
+use super::writing::WritingFileAdapter;
+use super::xml::write_lines;
+use super::*;
+use anyhow::Result;
+use lazy_static::lazy_static;
+use regex::{Captures, Regex};
+use std::io::{BufWriter, Read, Write};
+use tokio::io::AsyncWrite;
+use tokio_util::io::SyncIoBridge;
+
+static EXTENSIONS: &[&str] = &["srt", "vtt", "ass", "ssa"];
+static MIME_TYPES: &[&str] = &["application/x-subrip", "text/vtt", "text/x-ssa"];
+/// `[hours:]minutes:seconds.fraction`, with a comma in srt files
+const TIME: &str = r"(?:(\d+):)?(\d{1,2}):(\d{1,2})[.,](\d{1,3})";
+
+lazy_static! {
+    static ref METADATA: AdapterMeta = AdapterMeta {
+        name: "subtitles".to_owned(),
+        version: 1,
+        description: "Reads subtitle files (srt, vtt, ass, ssa) and prefixes each line with its time range, without styling tags".to_owned(),
+        recurses: false,
+        fast_matchers: EXTENSIONS
+            .iter()
+            .map(|s| FastFileMatcher::FileExtension(s.to_string()))
+            .collect(),
+        slow_matchers: Some(
+            MIME_TYPES
+                .iter()
+                .map(|s| FileMatcher::MimeType(s.to_string()))
+                .collect()
+        ),
+        keep_fast_matchers_if_accurate: true,
+        disabled_by_default: false
+    };
+    static ref CUE_TIMES: Regex = Regex::new(&format!(r"^\s*{}\s*-->\s*{}", TIME, TIME)).unwrap();
+    static ref ASS_TIME: Regex = Regex::new(&format!("^{}$", TIME)).unwrap();
+    static ref HTML_TAG: Regex = Regex::new(r"<[^>]*>").unwrap();
+    static ref ASS_OVERRIDE: Regex = Regex::new(r"\{[^}]*\}").unwrap();
+}
+
+#[derive(Default, Clone)]
+pub struct SubtitlesAdapter;
+
+impl SubtitlesAdapter {
+    pub fn new() -> Self {
+        Self
+    }
+}
+impl GetMetadata for SubtitlesAdapter {
+    fn metadata(&self) -> &AdapterMeta {
+        &METADATA
+    }
+}
+
+/// Formats the time captured at group `i` like the webvtt output of ffmpeg, e.g. `01:02.500`
+fn format_time(c: &Captures, i: usize) -> String {
+    let number = |i: usize| c.get(i).map_or(0, |m| m.as_str().parse().unwrap_or(0));
+    // fractions are milliseconds in srt and vtt, but centiseconds in ass
+    let fraction = c.get(i + 3).map_or("", |m| m.as_str());
+    let millis: u64 = format!("{fraction:0<3}").parse().unwrap_or(0);
+    let (hours, minutes, seconds) = (number(i), number(i + 1), number(i + 2));
+    match hours {
+        0 => format!("{minutes:02}:{seconds:02}.{millis:03}"),
+        _ => format!("{hours:02}:{minutes:02}:{seconds:02}.{millis:03}"),
+    }
+}
+
+fn decode_entities(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&nbsp;", " ")
+        .replace("&lrm;", "")
+        .replace("&rlm;", "")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+/// Writes the cues of srt and vtt files.
+///
+/// Lines that are not in a cue (numbers, identifiers, notes and styles) are skipped.
+fn write_cues(out: &mut impl Write, text: &str) -> Result<()> {
+    let mut time: Option<String> = None;
+    for line in text.lines() {
+        if let Some(c) = CUE_TIMES.captures(line) {
+            time = Some(format!("{} --> {}", format_time(&c, 1), format_time(&c, 5)));
+        } else if line.trim().is_empty() {
+            time = None;
+        } else if let Some(time) = &time {
+            let line = ASS_OVERRIDE.replace_all(line, "");
+            let line = decode_entities(&HTML_TAG.replace_all(&line, ""));
+            write_lines(out, &format!("{time}: "), &line)?;
+        }
+    }
+    Ok(())
+}
+
+/// Writes the dialogue lines of the `[Events]` section of ass and ssa files
+fn write_ass(out: &mut impl Write, text: &str) -> Result<()> {
+    let mut in_events = false;
+    // the default for files without a format line
+    let mut format: Vec<String> = [
+        "Layer", "Start", "End", "Style", "Name", "MarginL", "MarginR", "MarginV", "Effect", "Text",
+    ]
+    .map(str::to_owned)
+    .to_vec();
+    for line in text.lines().map(str::trim) {
+        if line.starts_with('[') {
+            in_events = line.eq_ignore_ascii_case("[events]");
+            continue;
+        }
+        if !in_events {
+            continue;
+        }
+        if let Some(fields) = line.strip_prefix("Format:") {
+            format = fields.split(',').map(|f| f.trim().to_owned()).collect();
+        } else if let Some(fields) = line.strip_prefix("Dialogue:") {
+            // the text is the last field and can contain commas
+            let fields: Vec<&str> = fields.splitn(format.len(), ',').collect();
+            let field = |name: &str| {
+                format
+                    .iter()
+                    .position(|f| f == name)
+                    .and_then(|i| fields.get(i))
+                    .map(|f| f.trim())
+            };
+            let time = |name: &str| {
+                let c = ASS_TIME.captures(field(name)?)?;
+                Some(format_time(&c, 1))
+            };
+            let (Some(start), Some(end), Some(text)) = (time("Start"), time("End"), field("Text"))
+            else {
+                continue;
+            };
+            let text = ASS_OVERRIDE
+                .replace_all(text, "")
+                .replace("\\N", "\n")
+                .replace("\\n", "\n")
+                .replace("\\h", " ");
+            write_lines(out, &format!("{start} --> {end}: "), &text)?;
+        }
+    }
+    Ok(())
+}
+
+fn synchronous_dump_subtitles(ai: AdaptInfo, out: impl Write) -> Result<()> {
+    let mut data = Vec::new();
+    SyncIoBridge::new(ai.inp).read_to_end(&mut data)?;
+    // older subtitle files are often in a legacy encoding
+    let text = match std::str::from_utf8(&data) {
+        Ok(text) => text.trim_start_matches('\u{feff}').into(),
+        Err(_) => encoding_rs::WINDOWS_1252.decode(&data).0,
+    };
+    let mut out = BufWriter::new(out);
+    if text.trim_start().starts_with("[Script Info]") {
+        write_ass(&mut out, &text)?;
+    } else {
+        write_cues(&mut out, &text)?;
+    }
+    out.flush()?;
+    Ok(())
+}
+
+#[async_trait]
+impl WritingFileAdapter for SubtitlesAdapter {
+    async fn adapt_write(
+        ai: AdaptInfo,
+        _detection_reason: &FileMatcher,
+        oup: Pin<Box<dyn AsyncWrite + Send>>,
+    ) -> Result<()> {
+        let oup_sync = SyncIoBridge::new(oup);
+        tokio::task::spawn_blocking(|| synchronous_dump_subtitles(ai, oup_sync))
+            .await?
+            .context("in synchronous subtitles task")?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use pretty_assertions::assert_eq;
+
+    #[tokio::test]
+    async fn srt() -> Result<()> {
+        assert_eq!(
+            adapt_test_file(&SubtitlesAdapter::new(), "hello.srt", true).await?,
+            "PREFIX:00:01.000 --> 00:04.074: Hello world!
+PREFIX:00:01.000 --> 00:04.074: Second line
+PREFIX:01:02:03.500 --> 01:02:05.000: Smith & Wesson
+PREFIX:
+"
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn vtt() -> Result<()> {
+        assert_eq!(
+            adapt_test_file(&SubtitlesAdapter::new(), "hello.vtt", true).await?,
+            "PREFIX:00:01.000 --> 00:04.000: We are in New York City
+PREFIX:00:01.000 --> 00:04.000: and it's raining <3
+PREFIX:00:05.250 --> 00:07.000: Second cue
+PREFIX:
+"
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn ass() -> Result<()> {
+        assert_eq!(
+            adapt_test_file(&SubtitlesAdapter::new(), "hello.ass", true).await?,
+            "PREFIX:00:01.000 --> 00:04.500: Hello, world
+PREFIX:00:01.000 --> 00:04.500: second line
+PREFIX:01:02:03.050 --> 01:02:05.000: Text, with commas
+PREFIX:
+"
+        );
+        Ok(())
+    }
+}
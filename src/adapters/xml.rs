@@ -0,0 +1,71 @@
+//! Helpers for adapters that extract text from XML based document formats.
+use anyhow::Result;
+use quick_xml::escape::resolve_predefined_entity;
+use quick_xml::events::{BytesStart, Event};
+use std::borrow::Cow;
+use std::io::Write;
+
+/// Returns the text of a text, CDATA or entity reference event, or `None` for any other event
+pub fn event_text<'a>(event: &'a Event) -> Result<Option<Cow<'a, str>>> {
+    Ok(match event {
+        Event::Text(t) => Some(t.decode()?),
+        Event::CData(t) => Some(t.decode()?),
+        Event::GeneralRef(r) => match r.resolve_char_ref()? {
+            Some(c) => Some(Cow::Owned(c.to_string())),
+            None => resolve_predefined_entity(&r.decode()?).map(Cow::Borrowed),
+        },
+        _ => None,
+    })
+}
+
+/// Returns the value of the attribute with the given name, ignoring its namespace prefix
+pub fn attribute(e: &BytesStart, local_name: &[u8]) -> Result<Option<String>> {
+    for a in e.attributes() {
+        let a = a?;
+        if a.key.local_name().as_ref() == local_name {
+            return Ok(Some(a.decode_and_unescape_value(e.decoder())?.into_owned()));
+        }
+    }
+    Ok(None)
+}
+
+/// Writes each non-empty line of `text` with the given prefix
+pub fn write_lines(out: &mut impl Write, prefix: &str, text: &str) -> Result<()> {
+    for line in text.lines().map(str::trim_end).filter(|l| !l.is_empty()) {
+        writeln!(out, "{prefix}{line}")?;
+    }
+    Ok(())
+}
+
+/// Resolves a path within a zip package (e.g. a relationship target) relative to the part it appears in
+pub fn resolve_target(source_part: &str, target: &str) -> String {
+    let mut path: Vec<&str> = match target.strip_prefix('/') {
+        Some(_) => vec![],
+        None => source_part.split('/').collect(),
+    };
+    path.pop();
+    for segment in target.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                path.pop();
+            }
+            segment => path.push(segment),
+        }
+    }
+    path.join("/")
+}
+
+/// Converts a zero based column index to its name (A, B, ..., Z, AA, ...)
+pub fn column_name(mut index: u32) -> String {
+    let mut name = Vec::new();
+    loop {
+        name.push(b'A' + (index % 26) as u8);
+        if index < 26 {
+            break;
+        }
+        index = index / 26 - 1;
+    }
+    name.reverse();
+    String::from_utf8(name).unwrap_or_default()
+}
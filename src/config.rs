@@ -160,6 +160,10 @@ pub struct RgaConfig {
     #[structopt(long = "--rga-no-prefix-filenames")]
     pub no_prefix_filenames: bool,
 
+    #[serde(default, skip_serializing_if = "is_default")]
+    #[structopt(flatten)]
+    pub adapter_options: AdapterOptions,
+
     #[serde(default, skip_serializing_if = "is_default")]
     #[structopt(skip)] // config file only
     pub custom_adapters: Option<Vec<CustomAdapterConfig>>,
@@ -252,6 +256,51 @@ pub struct CacheConfig {
     pub path: CachePath,
 }
 
+/// Options that change the output of specific adapters.
+///
+/// All of these are part of the cache key.
+#[derive(StructOpt, Debug, Deserialize, Serialize, JsonSchema, Default, Clone, PartialEq)]
+pub struct AdapterOptions {
+    /// Include the formulas of spreadsheet cells next to their computed values.
+    ///
+    /// By default, the spreadsheet adapter only outputs the value of each cell.
+    /// With this flag, cells that are computed by a formula are output as `Sheet1!C1: 147 [=A1*B1]`.
+    #[serde(default, skip_serializing_if = "is_default")]
+    #[structopt(long = "--rga-spreadsheet-formulas", hidden_short_help = true)]
+    pub spreadsheet_formulas: bool,
+
+    /// Pass the image outputs of Jupyter notebook cells on to the image adapters.
+    ///
+    /// Only useful together with an adapter that reads images, e.g. ocr or imagemeta.
+    #[serde(default, skip_serializing_if = "is_default")]
+    #[structopt(long = "--rga-notebook-images", hidden_short_help = true)]
+    pub notebook_images: bool,
+
+    /// Only dump these columns of Parquet and Arrow files (comma-separated).
+    ///
+    /// By default, the columnar adapter outputs every top-level column of each row.
+    #[serde(default, skip_serializing_if = "is_default")]
+    #[structopt(
+        long = "--rga-columnar-columns",
+        require_equals = true,
+        require_delimiter = true,
+        hidden_short_help = true
+    )]
+    pub columnar_columns: Vec<String>,
+
+    /// Append these fields of systemd journal entries to each line (comma-separated).
+    ///
+    /// E.g. `--rga-journal-fields=_HOSTNAME,_PID,PRIORITY`. By default only the timestamp, unit and MESSAGE are output.
+    #[serde(default, skip_serializing_if = "is_default")]
+    #[structopt(
+        long = "--rga-journal-fields",
+        require_equals = true,
+        require_delimiter = true,
+        hidden_short_help = true
+    )]
+    pub journal_fields: Vec<String>,
+}
+
 static RGA_CONFIG: &str = "RGA_CONFIG";
 
 use serde_json::Value;
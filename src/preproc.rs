@@ -155,6 +155,7 @@ async fn adapt_caching(
     let mut cache = cache.context("No cache?")?;
     let cache_key = CacheKey::new(
         ai.postprocess,
+        &ai.config,
         &ai.filepath_hint,
         adapter.as_ref(),
         &active_adapters,
@@ -1,9 +1,17 @@
-use crate::{adapters::FileAdapter, preproc::ActiveAdapters};
+use crate::{
+    adapters::FileAdapter,
+    config::{AdapterOptions, RgaConfig},
+    preproc::ActiveAdapters,
+};
 use anyhow::{Context, Result};
 use log::warn;
 use path_clean::PathClean;
 use rusqlite::{OptionalExtension, named_params};
-use std::{path::Path, time::UNIX_EPOCH};
+use std::{
+    hash::{DefaultHasher, Hash, Hasher},
+    path::Path,
+    time::UNIX_EPOCH,
+};
 use tokio_rusqlite::Connection;
 
 static SCHEMA_VERSION: i32 = 3;
@@ -19,6 +27,7 @@ pub struct CacheKey {
 impl CacheKey {
     pub fn new(
         postprocess: bool,
+        config: &RgaConfig,
         filepath_hint: &Path,
         adapter: &dyn FileAdapter,
         active_adapters: &ActiveAdapters,
@@ -37,12 +46,19 @@ impl CacheKey {
         } else {
             "null".to_string()
         };
+        let mut config_hash = if postprocess {
+            "a41e2e9".to_string()
+        } else {
+            "f1502a3".to_string()
+        };
+        if config.adapter_options != AdapterOptions::default() {
+            // the options are hashed as a whole so that new ones can't be left out of the key
+            let mut hasher = DefaultHasher::new();
+            serde_json::to_string(&config.adapter_options)?.hash(&mut hasher);
+            config_hash.push_str(&format!("+{:016x}", hasher.finish()));
+        }
         Ok(Self {
-            config_hash: if postprocess {
-                "a41e2e9".to_string()
-            } else {
-                "f1502a3".to_string()
-            }, // todo: when we add more config options that affect caching, create a struct and actually hash it
+            config_hash,
             adapter: adapter.metadata().name.clone(),
             adapter_version: adapter.metadata().version,
             file_path: filepath_hint.clean().to_string_lossy().to_string(),
@@ -205,4 +221,21 @@ mod test {
         // db.set();
         Ok(())
     }
+
+    #[test]
+    fn adapter_options_change_key() -> anyhow::Result<()> {
+        let path = crate::test_utils::test_data_dir().join("hello.plist");
+        let adapter = crate::adapters::plist::PlistAdapter::new();
+        let key = |config: &RgaConfig| {
+            CacheKey::new(true, config, &path, &adapter, &vec![]).map(|k| k.config_hash)
+        };
+        let mut config = RgaConfig::default();
+        let default_key = key(&config)?;
+        config.adapter_options.journal_fields = vec!["_PID".to_string()];
+        let fields_key = key(&config)?;
+        config.adapter_options.journal_fields = vec!["PRIORITY".to_string()];
+        assert_ne!(default_key, fields_key);
+        assert_ne!(fields_key, key(&config)?);
+        Ok(())
+    }
 }
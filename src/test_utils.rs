@@ -1,11 +1,13 @@
 use crate::{
     adapted_iter::AdaptedFilesIterBox,
     adapters::{
-        AdaptInfo, ReadBox,
+        AdaptInfo, FileAdapter, ReadBox,
         custom::{BUILTIN_SPAWNING_ADAPTERS, CustomSpawningFileAdapter},
+        pdf,
     },
     config::RgaConfig,
     matching::{FastFileMatcher, FileMatcher},
+    preproc::loop_adapt,
     recurse::concat_read_streams,
 };
 use anyhow::Result;
@@ -64,6 +66,46 @@ pub async fn adapted_to_vec(adapted: AdaptedFilesIterBox) -> Result<Vec<u8>> {
     Ok(buf)
 }
 
+/// Runs an adapter (and the adapters for its outputs) on a file in the test data dir.
+///
+/// If `real_file` is false, the file is passed like a file inside an archive.
+pub async fn adapt_test_file(
+    adapter: &dyn FileAdapter,
+    name: &str,
+    real_file: bool,
+) -> Result<String> {
+    adapt_test_file_with_config(adapter, name, real_file, RgaConfig::default()).await
+}
+
+pub async fn adapt_test_file_with_config(
+    adapter: &dyn FileAdapter,
+    name: &str,
+    real_file: bool,
+    config: RgaConfig,
+) -> Result<String> {
+    let filepath = test_data_dir().join(name);
+    let inp = Box::pin(File::open(&filepath).await?);
+    let (mut a, d) = simple_adapt_info_full(&filepath, inp, real_file);
+    a.config = config;
+    let o = adapted_to_vec(loop_adapt(adapter, d, a).await?).await?;
+    Ok(String::from_utf8(o)?)
+}
+
+/// The text of short.pdf with each line prefixed with `prefix`.
+///
+/// Depends on whether pdftotext is installed, since otherwise the native pdf adapter is used.
+pub fn short_pdf_text(prefix: &str) -> String {
+    let lines: &[&str] = if pdf::pdftotext_found() {
+        &["hello world", "this is just a test.", "", "1", "", ""]
+    } else {
+        &["hello world", "", "this is just a test.", " 1", ""]
+    };
+    lines
+        .iter()
+        .map(|line| format!("{prefix}Page 1: {line}\n"))
+        .collect()
+}
+
 pub fn poppler_adapter() -> CustomSpawningFileAdapter {
     let adapter = BUILTIN_SPAWNING_ADAPTERS
         .iter()